@@ -0,0 +1,270 @@
+//! Abilities are special actions, like casting a spell, that entities can take in place of moving
+//! or attacking. Each one has an energy cost and a cooldown, so that they can't be spammed.
+use specs::{prelude::*, Component};
+
+use crate::components::{DamageType, StatusKind};
+use crate::geometry::*;
+use crate::map::Map;
+
+/// How an ability picks what it affects.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Targeting {
+    /// Only affects the user.
+    OnSelf,
+    /// Affects whatever is on an adjacent tile.
+    Adjacent,
+    /// Fires a projectile at a point, affecting whatever it hits.
+    Ranged { range: i32 },
+    /// Affects everything within `radius` tiles of a point that's at most `range` tiles away.
+    Area { range: i32, radius: i32 },
+    /// Affects everything in a cone of length `radius`, aimed at a point.
+    Cone { radius: i32 },
+}
+
+/// What an ability does to each entity it affects.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AbilityEffect {
+    Damage { amount: i32, kind: DamageType },
+    Heal { amount: i32 },
+    Status { kind: StatusKind, duration: i32 },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Ability {
+    pub name: &'static str,
+    /// How much energy using the ability takes.
+    pub cost: i32,
+    /// How many of the user's turns have to pass before the ability can be used again.
+    pub cooldown: i32,
+    pub targeting: Targeting,
+    pub effect: AbilityEffect,
+}
+
+impl Ability {
+    /// Whether an entity standing at `user` can use this ability on `target`. Area effects have to
+    /// be centred on an open tile that the user can see.
+    pub fn can_target(&self, map: &Map, user: WorldPoint, target: WorldPoint) -> bool {
+        match self.targeting {
+            Targeting::OnSelf => target == user,
+            Targeting::Adjacent => distance(user, target) == 1,
+            Targeting::Ranged { range } => target != user && distance(user, target) <= range,
+            Targeting::Area { range, .. } => {
+                distance(user, target) <= range
+                    && map.contains(target)
+                    && !map.tile(target).is_solid()
+                    && map.has_line_of_sight(user, target)
+            }
+            Targeting::Cone { .. } => target != user,
+        }
+    }
+
+    /// The tiles that using this ability from `user` on `target` would affect. Area and cone
+    /// effects don't go through walls.
+    pub fn affected_tiles(
+        &self,
+        map: &Map,
+        user: WorldPoint,
+        target: WorldPoint,
+    ) -> Vec<WorldPoint> {
+        match self.targeting {
+            Targeting::OnSelf => vec![user],
+            Targeting::Adjacent => vec![target],
+            Targeting::Ranged { range } => map
+                .line_of_fire(user, target, range)
+                .last()
+                .copied()
+                .into_iter()
+                .collect(),
+            Targeting::Area { radius, .. } => map.blast_area(target, radius),
+            Targeting::Cone { radius } => map.cone_area(user, target, radius),
+        }
+    }
+}
+
+pub const FIREBOLT: Ability = Ability {
+    name: "firebolt",
+    cost: 3,
+    cooldown: 2,
+    targeting: Targeting::Ranged { range: 6 },
+    effect: AbilityEffect::Damage {
+        amount: 4,
+        kind: DamageType::Fire,
+    },
+};
+
+pub const FROST_BURST: Ability = Ability {
+    name: "frost burst",
+    cost: 5,
+    cooldown: 6,
+    targeting: Targeting::Area {
+        range: 5,
+        radius: 1,
+    },
+    effect: AbilityEffect::Damage {
+        amount: 3,
+        kind: DamageType::Cold,
+    },
+};
+
+pub const FIREBALL: Ability = Ability {
+    name: "fireball",
+    cost: 6,
+    cooldown: 8,
+    targeting: Targeting::Area {
+        range: 7,
+        radius: 2,
+    },
+    effect: AbilityEffect::Damage {
+        amount: 5,
+        kind: DamageType::Fire,
+    },
+};
+
+pub const FLAME_BREATH: Ability = Ability {
+    name: "flame breath",
+    cost: 4,
+    cooldown: 5,
+    targeting: Targeting::Cone { radius: 3 },
+    effect: AbilityEffect::Damage {
+        amount: 3,
+        kind: DamageType::Fire,
+    },
+};
+
+pub const SHIELD_BASH: Ability = Ability {
+    name: "shield bash",
+    cost: 2,
+    cooldown: 4,
+    targeting: Targeting::Adjacent,
+    effect: AbilityEffect::Status {
+        kind: StatusKind::Stun,
+        duration: 2,
+    },
+};
+
+pub const REND: Ability = Ability {
+    name: "rend",
+    cost: 2,
+    cooldown: 3,
+    targeting: Targeting::Adjacent,
+    effect: AbilityEffect::Status {
+        kind: StatusKind::Bleed { damage: 1 },
+        duration: 4,
+    },
+};
+
+pub const QUICKEN: Ability = Ability {
+    name: "quicken",
+    cost: 3,
+    cooldown: 8,
+    targeting: Targeting::OnSelf,
+    effect: AbilityEffect::Status {
+        kind: StatusKind::Haste { amount: 4 },
+        duration: 5,
+    },
+};
+
+pub const SECOND_WIND: Ability = Ability {
+    name: "second wind",
+    cost: 4,
+    cooldown: 10,
+    targeting: Targeting::OnSelf,
+    effect: AbilityEffect::Heal { amount: 6 },
+};
+
+pub const VENOM_SPIT: Ability = Ability {
+    name: "venom spit",
+    cost: 3,
+    cooldown: 4,
+    targeting: Targeting::Ranged { range: 5 },
+    effect: AbilityEffect::Status {
+        kind: StatusKind::Poison { damage: 2 },
+        duration: 4,
+    },
+};
+
+/// Every ability in the game.
+pub const ALL: [Ability; 9] = [
+    FIREBOLT,
+    FROST_BURST,
+    FIREBALL,
+    FLAME_BREATH,
+    SHIELD_BASH,
+    REND,
+    QUICKEN,
+    SECOND_WIND,
+    VENOM_SPIT,
+];
+
+/// Looks up an ability by its name, for when abilities are referred to from data files.
+pub fn named(name: &str) -> Option<Ability> {
+    ALL.iter().find(|ability| ability.name == name).copied()
+}
+
+/// An ability that an entity knows, along with its current cooldown.
+#[derive(Copy, Clone, Debug)]
+pub struct KnownAbility {
+    pub ability: Ability,
+    /// How many more turns until the ability can be used again. 0 means it's ready.
+    pub cooldown: i32,
+}
+
+/// The abilities that an entity can use, in the order they're bound to keys.
+#[derive(Component, Clone, Debug, Default)]
+pub struct Abilities(pub Vec<KnownAbility>);
+
+impl Abilities {
+    pub fn new(abilities: &[Ability]) -> Self {
+        Abilities(
+            abilities
+                .iter()
+                .map(|ability| KnownAbility {
+                    ability: *ability,
+                    cooldown: 0,
+                })
+                .collect(),
+        )
+    }
+}
+
+/// The pool that ability costs are paid from. Regenerates by 1 every turn.
+#[derive(Component, Copy, Clone, Debug)]
+pub struct Energy {
+    pub current: i32,
+    pub max: i32,
+}
+
+impl Energy {
+    pub fn new(max: i32) -> Self {
+        Energy { current: max, max }
+    }
+}
+
+/// Indicates that this entity wants to use the ability at the given index of its Abilities.
+/// Resolved by AbilitySystem.
+#[derive(Component, Copy, Clone, Debug)]
+pub struct AbilityIntent {
+    pub ability: usize,
+    pub target: WorldPoint,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::TileType;
+
+    #[test]
+    fn area_abilities_need_line_of_sight() {
+        let mut map = Map::new(10, 10);
+        for y in 0..10 {
+            map.set_tile((4, y).into(), TileType::Wall);
+        }
+        let user = (2, 5).into();
+        assert!(FIREBALL.can_target(&map, user, (3, 5).into()));
+        assert!(!FIREBALL.can_target(&map, user, (4, 5).into()));
+        assert!(!FIREBALL.can_target(&map, user, (6, 5).into()));
+
+        map.set_tile((4, 5).into(), TileType::Floor);
+        assert!(FIREBALL.can_target(&map, user, (6, 5).into()));
+    }
+}
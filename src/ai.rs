@@ -1,4 +1,7 @@
-use crate::geometry::WorldVector;
+use crate::components::{
+    CombatStats, Consumable, Faction, Inventory, ItemEffect, Perception, Relation,
+};
+use crate::geometry::{distance, WorldPoint, WorldVector};
 use crate::{Action, Position};
 use specs::{prelude::*, Component};
 
@@ -9,24 +12,104 @@ pub trait AI: Send + Sync + std::fmt::Debug {
     fn decide(&mut self, world: &World, me: Entity) -> Option<Action>;
 }
 
-/// This AI just moves towards its target as fast as possible.
-#[derive(Copy, Clone, Debug)]
+/// Returns a healing item in the entity's inventory if it's badly hurt (at or below half health)
+/// and has one, so that AIs can decide to use it.
+pub fn healing_item(world: &World, me: Entity) -> Option<Entity> {
+    let stats = world.read_component::<CombatStats>();
+    let stats = stats.get(me)?;
+    if stats.hp * 2 > stats.max_hp {
+        return None;
+    }
+    let inventories = world.read_component::<Inventory>();
+    let consumables = world.read_component::<Consumable>();
+    inventories.get(me)?.items.iter().copied().find(|item| {
+        matches!(
+            consumables.get(*item).map(|consumable| consumable.effect),
+            Some(ItemEffect::Heal { .. })
+        )
+    })
+}
+
+/// The motion that takes a step from `from` straight towards `to`.
+pub fn step_towards(from: WorldPoint, to: WorldPoint) -> WorldVector {
+    let delta = to - from;
+    WorldVector::new(delta.x.signum(), delta.y.signum())
+}
+
+/// Whether `target` is something that `me` should still be going after: it has to still exist,
+/// be hostile, and not be dead (corpses stick around, but lose their CombatStats).
+fn is_valid_target(world: &World, me: Entity, target: Entity) -> bool {
+    world.entities().is_alive(target)
+        && world.read_component::<Position>().contains(target)
+        && world.read_component::<CombatStats>().contains(target)
+        && Faction::between(&world.read_component::<Faction>(), me, target) == Relation::Hostile
+}
+
+/// Finds the closest entity that `me` is hostile towards and that can be fought.
+fn nearest_hostile(world: &World, me: Entity) -> Option<Entity> {
+    let positions = world.read_component::<Position>();
+    let stats = world.read_component::<CombatStats>();
+    let factions = world.read_component::<Faction>();
+    let my_pos = positions.get(me)?.0;
+    (&world.entities(), &positions, &stats)
+        .join()
+        .filter(|(entity, _, _)| Faction::between(&factions, me, *entity) == Relation::Hostile)
+        .min_by_key(|(_, position, _)| distance(my_pos, position.0))
+        .map(|(entity, _, _)| entity)
+}
+
+/// Keeps track of what an AI is going after. Targets can die or otherwise go away at any time, so
+/// this checks that the target is still valid every time it's asked for, and picks a new one if
+/// it isn't. Entities with Perception only go after what they can see.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TargetTracker {
+    target: Option<Entity>,
+}
+
+impl TargetTracker {
+    /// The current target and where it is, picking a new target if needed. Returns `None` if there
+    /// isn't anything to go after.
+    pub fn current(&mut self, world: &World, me: Entity) -> Option<(Entity, WorldPoint)> {
+        let perception = world.read_component::<Perception>();
+        let target = match perception.get(me) {
+            Some(perception) => perception
+                .target()
+                .filter(|target| is_valid_target(world, me, *target)),
+            None => self
+                .target
+                .filter(|target| is_valid_target(world, me, *target))
+                .or_else(|| nearest_hostile(world, me)),
+        };
+        self.target = target;
+        let target = target?;
+        let position = world.read_component::<Position>().get(target)?.0;
+        Some((target, position))
+    }
+}
+
+/// This AI picks the closest hostile entity, and then moves towards it as fast as possible. If
+/// it's badly hurt and has something to heal itself with, it'll use that first. If there's
+/// nothing to go after, it stays put.
+#[derive(Copy, Clone, Debug, Default)]
 pub struct Swarm {
-    pub target: Entity,
+    pub target: TargetTracker,
 }
 
 impl AI for Swarm {
     fn decide(&mut self, world: &World, me: Entity) -> Option<Action> {
-        let pos_component = world.read_component::<Position>();
-        let to_target =
-            pos_component.get(self.target).unwrap().0 - pos_component.get(me).unwrap().0;
-        if to_target.x.abs() <= 1 && to_target.y.abs() <= 1 {
-            Some(Action::Attack {
-                target: self.target,
-            })
+        if let Some(item) = healing_item(world, me) {
+            return Some(Action::UseItem { item });
+        }
+        let my_pos = world.read_component::<Position>().get(me)?.0;
+        let (target, target_pos) = match self.target.current(world, me) {
+            Some(target) => target,
+            None => return Some(Action::Wait),
+        };
+        if distance(my_pos, target_pos) <= 1 {
+            Some(Action::Attack { target })
         } else {
             Some(Action::Move {
-                motion: WorldVector::new(to_target.x.signum(), to_target.y.signum()),
+                motion: step_towards(my_pos, target_pos),
             })
         }
     }
@@ -0,0 +1,378 @@
+//! Behavior trees: a way of putting AIs together out of small, reusable pieces instead of writing
+//! each one by hand. A tree is made of composite nodes (sequences and selectors), conditions,
+//! and actions; the actions at the leaves are what actually produce an `engine::Action`. Trees can
+//! be written out in the monster data files.
+use rand::seq::SliceRandom;
+use serde::Deserialize;
+use specs::prelude::*;
+
+use crate::ai::{healing_item, step_towards, TargetTracker, AI};
+use crate::components::{
+    CombatStats, Home, LastAttacker, Name, Patrol, Perception, Position, RangedWeapon,
+};
+use crate::engine::{Action, GameRng};
+use crate::event_log::{Event, EventLog};
+use crate::geometry::{distance, points_within, WorldPoint, WorldVector};
+use crate::map::Map;
+use crate::pack;
+
+/// A check that a Condition node makes.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Condition {
+    /// Whether there's anything hostile to go after.
+    HasTarget,
+    /// Whether the target is at most `distance` tiles away.
+    TargetWithin { distance: i32 },
+    /// Whether the entity's hp is below `percent` of its maximum.
+    HpBelow { percent: i32 },
+    /// Whether the entity has a ranged weapon with ammunition left.
+    HasAmmo,
+}
+
+/// Something that an Action node does.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Task {
+    /// Attacks the target if it's adjacent.
+    AttackTarget,
+    /// Takes a step towards the target.
+    ChaseTarget,
+    /// Drinks a healing item, if the entity is badly hurt and has one.
+    UseHealingItem,
+    /// Morale check: if the entity's hp is below `below_percent` of its maximum, it runs away from
+    /// whoever last hurt it. Fails if the entity isn't hurt that badly, or if it's cornered and has
+    /// nowhere to run.
+    Flee { below_percent: i32 },
+    /// Backs away from the target if it's closer than `distance` tiles. Fails if the target is far
+    /// enough away already, or if there's nowhere to back away to.
+    KeepDistance { distance: i32 },
+    /// Fires the entity's ranged weapon at the target. Fails if the entity has no ammunition, the
+    /// target is out of range, or something is in the way.
+    ShootTarget,
+    /// Heads for where the entity last saw an enemy that it's lost track of.
+    Investigate,
+    /// Takes a random step, or stays put. If `radius` is set, the entity stays within that many
+    /// tiles of its Home, and heads back there if it's strayed too far.
+    Wander { radius: Option<i32> },
+    /// Walks the entity's Patrol route. Fails if the entity doesn't have one, or if none of its
+    /// waypoints can be reached.
+    Patrol,
+    /// Does nothing for a turn. Always succeeds.
+    Wait,
+}
+
+/// A node in a behavior tree.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Node {
+    /// Runs its children in order until one of them fails or acts.
+    Sequence { children: Vec<Node> },
+    /// Runs its children in order until one of them succeeds or acts.
+    Selector { children: Vec<Node> },
+    /// Succeeds if the condition holds, and fails otherwise.
+    Condition { check: Condition },
+    /// Acts if it can, and fails otherwise.
+    Action { task: Task },
+}
+
+/// The result of running a node.
+#[derive(Clone, Debug)]
+pub enum Outcome {
+    Success,
+    Failure,
+    /// The node decided what to do this turn. This stops the whole tree.
+    Act(Action),
+}
+
+/// State that's kept between turns, and shared between all the nodes in a tree.
+#[derive(Clone, Debug, Default)]
+pub struct Blackboard {
+    pub target: TargetTracker,
+    pub morale: Morale,
+}
+
+/// Whether an entity is standing its ground or running away. Updated by the Flee task.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Morale {
+    #[default]
+    Steady,
+    Fleeing,
+    /// Was running away, but had nowhere left to go.
+    Cornered,
+}
+
+/// What a node gets to look at while it runs.
+pub struct Context<'a> {
+    pub world: &'a World,
+    pub me: Entity,
+    pub blackboard: &'a mut Blackboard,
+}
+
+impl Condition {
+    fn check(&self, ctx: &mut Context) -> bool {
+        match self {
+            Condition::HasTarget => ctx.blackboard.target.current(ctx.world, ctx.me).is_some(),
+            Condition::TargetWithin { distance: max } => {
+                let my_pos = match ctx.world.read_component::<Position>().get(ctx.me) {
+                    Some(position) => position.0,
+                    None => return false,
+                };
+                ctx.blackboard
+                    .target
+                    .current(ctx.world, ctx.me)
+                    .is_some_and(|(_, target_pos)| distance(my_pos, target_pos) <= *max)
+            }
+            Condition::HpBelow { percent } => ctx
+                .world
+                .read_component::<CombatStats>()
+                .get(ctx.me)
+                .is_some_and(|stats| stats.hp * 100 < stats.max_hp * percent),
+            Condition::HasAmmo => ctx
+                .world
+                .read_component::<RangedWeapon>()
+                .get(ctx.me)
+                .is_some_and(|weapon| weapon.ammo > 0),
+        }
+    }
+}
+
+impl Task {
+    fn run(&self, ctx: &mut Context) -> Option<Action> {
+        match self {
+            Task::AttackTarget => {
+                let my_pos = ctx.world.read_component::<Position>().get(ctx.me)?.0;
+                let (target, target_pos) = ctx.blackboard.target.current(ctx.world, ctx.me)?;
+                (distance(my_pos, target_pos) <= 1).then_some(Action::Attack { target })
+            }
+            Task::ChaseTarget => {
+                let my_pos = ctx.world.read_component::<Position>().get(ctx.me)?.0;
+                let (_, target_pos) = ctx.blackboard.target.current(ctx.world, ctx.me)?;
+                // Pack members spread out around the target rather than all taking the same path.
+                let goal = pack::surround(ctx.world, ctx.me, target_pos).unwrap_or(target_pos);
+                Some(Action::Move {
+                    motion: step_towards(my_pos, goal),
+                })
+            }
+            Task::Flee { below_percent } => {
+                let hurt = Condition::HpBelow {
+                    percent: *below_percent,
+                }
+                .check(ctx);
+                let (morale, step) = match ctx.blackboard.morale {
+                    _ if !hurt => (Morale::Steady, None),
+                    // Once cornered, the entity fights it out rather than trying to run again.
+                    Morale::Cornered => (Morale::Cornered, None),
+                    Morale::Steady | Morale::Fleeing => match flee_step(ctx) {
+                        Some(step) => (Morale::Fleeing, Some(step)),
+                        None => (Morale::Cornered, None),
+                    },
+                };
+                let event = match (ctx.blackboard.morale, morale) {
+                    (Morale::Steady, Morale::Fleeing) => Some(Event::Flee { who: ctx.me }),
+                    (Morale::Fleeing, Morale::Cornered) => Some(Event::Cornered { who: ctx.me }),
+                    _ => None,
+                };
+                if let Some(event) = event {
+                    let names = ctx.world.read_component::<Name>();
+                    ctx.world.fetch_mut::<EventLog>().log(event.format(&names));
+                }
+                ctx.blackboard.morale = morale;
+                step.map(|motion| Action::Move { motion })
+            }
+            Task::KeepDistance {
+                distance: preferred,
+            } => {
+                let my_pos = ctx.world.read_component::<Position>().get(ctx.me)?.0;
+                let (_, target_pos) = ctx.blackboard.target.current(ctx.world, ctx.me)?;
+                if distance(my_pos, target_pos) >= *preferred {
+                    return None;
+                }
+                step_away(ctx.world, ctx.me, target_pos).map(|motion| Action::Move { motion })
+            }
+            Task::ShootTarget => {
+                let my_pos = ctx.world.read_component::<Position>().get(ctx.me)?.0;
+                let (_, target_pos) = ctx.blackboard.target.current(ctx.world, ctx.me)?;
+                let weapon = *ctx.world.read_component::<RangedWeapon>().get(ctx.me)?;
+                let path = ctx
+                    .world
+                    .fetch::<Map>()
+                    .line_of_fire(my_pos, target_pos, weapon.range);
+                (weapon.ammo > 0 && path.last() == Some(&target_pos))
+                    .then_some(Action::RangedAttack { target: target_pos })
+            }
+            Task::Investigate => {
+                let my_pos = ctx.world.read_component::<Position>().get(ctx.me)?.0;
+                let last_known = ctx
+                    .world
+                    .read_component::<Perception>()
+                    .get(ctx.me)?
+                    .last_known()?;
+                (last_known != my_pos).then(|| Action::Move {
+                    motion: step_towards(my_pos, last_known),
+                })
+            }
+            Task::Wander { radius } => {
+                let my_pos = ctx.world.read_component::<Position>().get(ctx.me)?.0;
+                let home = ctx
+                    .world
+                    .read_component::<Home>()
+                    .get(ctx.me)
+                    .map(|home| home.0);
+                let bounds = radius.and_then(|radius| Some((home?, radius)));
+                if let Some((home, radius)) = bounds {
+                    if distance(my_pos, home) > radius {
+                        return Some(Action::Move {
+                            motion: step_towards(my_pos, home),
+                        });
+                    }
+                }
+                let map = ctx.world.fetch::<Map>();
+                let options: Vec<WorldPoint> = points_within(my_pos, 1)
+                    .into_iter()
+                    .filter(|point| {
+                        *point == my_pos || (map.contains(*point) && !map.is_blocked(*point))
+                    })
+                    .filter(|point| {
+                        bounds.is_none_or(|(home, radius)| distance(*point, home) <= radius)
+                    })
+                    .collect();
+                let step = *options.choose(&mut ctx.world.fetch_mut::<GameRng>().0)?;
+                Some(if step == my_pos {
+                    Action::Wait
+                } else {
+                    Action::Move {
+                        motion: step - my_pos,
+                    }
+                })
+            }
+            Task::Patrol => {
+                let my_pos = ctx.world.read_component::<Position>().get(ctx.me)?.0;
+                let mut patrols = ctx.world.write_component::<Patrol>();
+                let patrol = patrols.get_mut(ctx.me)?;
+                let map = ctx.world.fetch::<Map>();
+                // Waypoints that have been reached are done with. So are ones that can't be
+                // reached: something is standing on them, or there's a wall in the way.
+                let reachable = |waypoint: WorldPoint| {
+                    waypoint != my_pos
+                        && map.contains(waypoint)
+                        && !map.is_blocked(waypoint)
+                        && !map.tile(my_pos + step_towards(my_pos, waypoint)).is_solid()
+                };
+                for _ in 0..patrol.waypoints.len() {
+                    let waypoint = patrol.waypoints[patrol.next];
+                    if reachable(waypoint) {
+                        return Some(Action::Move {
+                            motion: step_towards(my_pos, waypoint),
+                        });
+                    }
+                    patrol.next = (patrol.next + 1) % patrol.waypoints.len();
+                }
+                None
+            }
+            Task::UseHealingItem => {
+                healing_item(ctx.world, ctx.me).map(|item| Action::UseItem { item })
+            }
+            Task::Wait => Some(Action::Wait),
+        }
+    }
+}
+
+/// The step that takes the entity furthest away from whatever it's running from, or `None` if
+/// there isn't a step that gets it any further away.
+fn flee_step(ctx: &mut Context) -> Option<WorldVector> {
+    let attacker = ctx
+        .world
+        .read_component::<LastAttacker>()
+        .get(ctx.me)
+        .and_then(|attacker| {
+            ctx.world
+                .read_component::<Position>()
+                .get(attacker.0)
+                .copied()
+        })
+        .map(|position| position.0);
+    let threat = match attacker {
+        Some(threat) => threat,
+        None => ctx.blackboard.target.current(ctx.world, ctx.me)?.1,
+    };
+    step_away(ctx.world, ctx.me, threat)
+}
+
+/// The step that takes `me` furthest away from `threat`, or `None` if there isn't a step that
+/// gets it any further away. This uses straight-line distance rather than `distance`, so that
+/// moving along a wall still counts as getting away.
+fn step_away(world: &World, me: Entity, threat: WorldPoint) -> Option<WorldVector> {
+    let my_pos = world.read_component::<Position>().get(me)?.0;
+    let map = world.fetch::<Map>();
+    points_within(my_pos, 1)
+        .into_iter()
+        .filter(|point| map.contains(*point) && !map.is_blocked(*point))
+        .filter(|point| (*point - threat).square_length() > (my_pos - threat).square_length())
+        .max_by_key(|point| (*point - threat).square_length())
+        .map(|point| point - my_pos)
+}
+
+impl Node {
+    pub fn run(&self, ctx: &mut Context) -> Outcome {
+        match self {
+            Node::Sequence { children } => {
+                for child in children {
+                    match child.run(ctx) {
+                        Outcome::Success => (),
+                        outcome => return outcome,
+                    }
+                }
+                Outcome::Success
+            }
+            Node::Selector { children } => {
+                for child in children {
+                    match child.run(ctx) {
+                        Outcome::Failure => (),
+                        outcome => return outcome,
+                    }
+                }
+                Outcome::Failure
+            }
+            Node::Condition { check } => {
+                if check.check(ctx) {
+                    Outcome::Success
+                } else {
+                    Outcome::Failure
+                }
+            }
+            Node::Action { task } => task.run(ctx).map_or(Outcome::Failure, Outcome::Act),
+        }
+    }
+}
+
+/// An AI that decides what to do by running a behavior tree. If the tree doesn't come up with
+/// an action, the entity waits.
+#[derive(Clone, Debug)]
+pub struct BehaviorTree {
+    root: Node,
+    blackboard: Blackboard,
+}
+
+impl BehaviorTree {
+    pub fn new(root: Node) -> Self {
+        BehaviorTree {
+            root,
+            blackboard: Blackboard::default(),
+        }
+    }
+}
+
+impl AI for BehaviorTree {
+    fn decide(&mut self, world: &World, me: Entity) -> Option<Action> {
+        let mut ctx = Context {
+            world,
+            me,
+            blackboard: &mut self.blackboard,
+        };
+        match self.root.run(&mut ctx) {
+            Outcome::Act(action) => Some(action),
+            Outcome::Success | Outcome::Failure => Some(Action::Wait),
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! Data-driven definitions of monsters and items. These are loaded from the files in
+//! `static/data`, so that adding a new monster, item or drop doesn't need a code change.
+use rand::Rng;
+use serde::Deserialize;
+use specs::{prelude::*, world::Builder};
+use std::collections::HashMap;
+
+use crate::ai::{AIComponent, Swarm};
+use crate::behavior::{BehaviorTree, Node};
+use crate::components::*;
+use crate::engine::GameRng;
+use crate::geometry::WorldPoint;
+use crate::pack::{PackMember, Packs};
+use crate::tiles::TileId;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ItemBlueprint {
+    pub name: String,
+    pub tile: TileId,
+    pub consumable: Option<ItemEffect>,
+    pub equippable: Option<Equippable>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct MonsterBlueprint {
+    pub name: String,
+    pub tile: TileId,
+    pub initiative: i32,
+    pub faction: Faction,
+    pub stats: BaseStats,
+    /// How far the monster can see. Monsters without this always know where their enemies are.
+    pub sight: Option<i32>,
+    /// The chance, in percent, that the monster starts out asleep. Only monsters with `sight` can
+    /// sleep.
+    #[serde(default)]
+    pub sleep_chance: i32,
+    /// If set, the monster regenerates 1 hp every this many turns.
+    pub regeneration: Option<i32>,
+    /// How much experience killing the monster is worth.
+    #[serde(default)]
+    pub experience: i32,
+    /// Pairs of damage types and how the monster reacts to them.
+    #[serde(default)]
+    pub resistances: Vec<(DamageType, Resistance)>,
+    pub inflicts: Option<InflictsStatus>,
+    pub ranged: Option<RangedWeapon>,
+    /// Ids of the items that the monster starts out carrying.
+    #[serde(default)]
+    pub inventory: Vec<String>,
+    #[serde(default)]
+    pub loot: Vec<LootDrop>,
+    /// The behavior tree that drives the monster. Monsters without one act like a Swarm.
+    pub behavior: Option<Node>,
+}
+
+/// All of the blueprints, keyed by id. This is stored as a resource so that systems can spawn
+/// things too.
+#[derive(Debug)]
+pub struct Blueprints {
+    pub items: HashMap<String, ItemBlueprint>,
+    pub monsters: HashMap<String, MonsterBlueprint>,
+}
+
+impl Blueprints {
+    /// Loads the blueprints that are bundled with the game. Panics if the data files are
+    /// malformed, or if a monster carries or drops an item that doesn't exist, since there's no
+    /// sensible way to continue without them.
+    pub fn load() -> Self {
+        let blueprints = Blueprints {
+            items: toml::from_str(include_str!("../static/data/items.toml"))
+                .expect("couldn't parse item blueprints"),
+            monsters: toml::from_str(include_str!("../static/data/monsters.toml"))
+                .expect("couldn't parse monster blueprints"),
+        };
+        for (id, monster) in &blueprints.monsters {
+            let carried = monster.inventory.iter();
+            let dropped = monster.loot.iter().map(|drop| &drop.item);
+            for item in carried.chain(dropped) {
+                if !blueprints.items.contains_key(item) {
+                    panic!("monster {} refers to unknown item {}", id, item);
+                }
+            }
+        }
+        blueprints
+    }
+
+    /// Adds the components for the item with the given id to the builder. Doesn't give it a
+    /// position, since the item might be going into an inventory. Returns `None` if there's no
+    /// such item.
+    pub fn build_item<B: Builder>(&self, id: &str, builder: B) -> Option<B> {
+        let blueprint = self.items.get(id)?;
+        let mut builder = builder
+            .with(Name {
+                name: blueprint.name.clone(),
+            })
+            .with(Visible {
+                tile_id: blueprint.tile,
+            })
+            .with(Item);
+        if let Some(effect) = blueprint.consumable {
+            builder = builder.with(Consumable { effect });
+        }
+        if let Some(equippable) = blueprint.equippable {
+            builder = builder.with(equippable);
+        }
+        Some(builder)
+    }
+
+    /// Spawns the item with the given id on the floor.
+    pub fn spawn_item(&self, world: &mut World, id: &str, position: WorldPoint) -> Entity {
+        self.build_item(id, world.create_entity())
+            .unwrap_or_else(|| panic!("no item blueprint named {}", id))
+            .with(Position(position))
+            .build()
+    }
+
+    /// Spawns the monster with the given id, and sends it walking along `waypoints` whenever it
+    /// isn't busy.
+    pub fn spawn_patrol(&self, world: &mut World, id: &str, waypoints: &[WorldPoint]) -> Entity {
+        let start = *waypoints.first().expect("patrol route has no waypoints");
+        let monster = self.spawn_monster(world, id, start);
+        world
+            .write_storage::<Patrol>()
+            .insert(monster, Patrol::new(waypoints.to_vec()))
+            .expect("couldn't set patrol route");
+        monster
+    }
+
+    /// Spawns one of the monster with the given id at each of `positions`, all in the same pack.
+    pub fn spawn_pack(&self, world: &mut World, id: &str, positions: &[WorldPoint]) -> Vec<Entity> {
+        let member = world.write_resource::<Packs>().create();
+        positions
+            .iter()
+            .map(|position| {
+                let monster = self.spawn_monster(world, id, *position);
+                world
+                    .write_storage::<PackMember>()
+                    .insert(monster, member)
+                    .expect("couldn't add pack member");
+                monster
+            })
+            .collect()
+    }
+
+    /// Spawns the monster with the given id, along with everything it's carrying. Its home is
+    /// wherever it was spawned.
+    pub fn spawn_monster(&self, world: &mut World, id: &str, position: WorldPoint) -> Entity {
+        let blueprint = self
+            .monsters
+            .get(id)
+            .unwrap_or_else(|| panic!("no monster blueprint named {}", id));
+        let asleep = world.fetch_mut::<GameRng>().0.gen_range(0, 100) < blueprint.sleep_chance;
+        let items: Vec<Entity> = blueprint
+            .inventory
+            .iter()
+            .map(|item| {
+                self.build_item(item, world.create_entity())
+                    .unwrap_or_else(|| panic!("no item blueprint named {}", item))
+                    .build()
+            })
+            .collect();
+        let mut builder = world
+            .create_entity()
+            .with(Name {
+                name: blueprint.name.clone(),
+            })
+            .with(Position(position))
+            .with(Home(position))
+            .with(Visible {
+                tile_id: blueprint.tile,
+            })
+            .with(blueprint.stats)
+            .with(blueprint.stats.derive(blueprint.stats.max_hp, &[]))
+            .with(Resistances(blueprint.resistances.iter().copied().collect()))
+            .with(Initiative::new(blueprint.initiative))
+            .with(blueprint.faction)
+            .with(Inventory {
+                capacity: items.len(),
+                items,
+            })
+            .with(LootTable(blueprint.loot.clone()))
+            .with(ExperienceValue(blueprint.experience))
+            .with(BlocksMovement)
+            .with(AIComponent(match &blueprint.behavior {
+                Some(root) => Box::new(BehaviorTree::new(root.clone())),
+                None => Box::new(Swarm::default()),
+            }));
+        if let Some(inflicts) = blueprint.inflicts {
+            builder = builder.with(inflicts);
+        }
+        if let Some(weapon) = blueprint.ranged {
+            builder = builder.with(weapon);
+        }
+        if let Some(sight_radius) = blueprint.sight {
+            let mut perception = Perception::new(sight_radius);
+            if asleep {
+                perception.awareness = Awareness::Asleep;
+            }
+            builder = builder.with(perception);
+        }
+        if let Some(interval) = blueprint.regeneration {
+            builder = builder.with(Regeneration::new(interval));
+        }
+        builder.build()
+    }
+}
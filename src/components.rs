@@ -1,6 +1,8 @@
 use crate::geometry::*;
 use crate::tiles::TileId;
+use serde::Deserialize;
 use specs::{prelude::*, Component};
+use std::collections::HashMap;
 
 /// Models turn order. Entities start out with a given initiative, which ticks down by 1 every
 /// turn. When it reaches 0, that entity is ready to move.
@@ -36,12 +38,144 @@ impl Initiative {
 #[storage(NullStorage)]
 pub struct Ready;
 
+/// A marker component for entities whose initiative reached 0 this turn, whether or not they
+/// actually get to act (they might be stunned, for example). Used for things that happen once per
+/// entity turn, like status effects ticking. Cleared by InitiativeSystem at the start of each turn.
+#[derive(Component, Default, Debug, Copy, Clone)]
+#[storage(NullStorage)]
+pub struct NewTurn;
+
+/// What an entity knows about its enemies.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Awareness {
+    /// Doesn't see anything until something wakes it up: a noise, or getting hurt.
+    Asleep,
+    /// Hasn't noticed anything.
+    Unaware,
+    /// Can see `target` right now, at `seen_at`.
+    Hunting { target: Entity, seen_at: WorldPoint },
+    /// Lost sight of its target, which was last seen at `last_known`. Gives up after
+    /// `turns_left` more turns.
+    Searching {
+        last_known: WorldPoint,
+        turns_left: i32,
+    },
+}
+
+/// The chance to notice an enemy, in percent, is clamped to this range, so that stealth never makes
+/// anything completely undetectable.
+const MIN_NOTICE_CHANCE: i32 = 5;
+const MAX_NOTICE_CHANCE: i32 = 100;
+
+/// The chance, in percent, that an entity with the given stats gets noticed when it comes into
+/// view or makes a noise.
+pub fn notice_chance(stats: Option<&CombatStats>) -> i32 {
+    (100 - stats.map_or(0, |stats| stats.stealth)).clamp(MIN_NOTICE_CHANCE, MAX_NOTICE_CHANCE)
+}
+
+/// Makes an entity only react to enemies that it can actually see. Updated by PerceptionSystem.
+/// Entities without this know where everything is at all times.
+#[derive(Component, Copy, Clone, Debug)]
+pub struct Perception {
+    /// How far the entity can see.
+    pub sight_radius: i32,
+    pub awareness: Awareness,
+}
+
+impl Perception {
+    pub fn new(sight_radius: i32) -> Self {
+        Perception {
+            sight_radius,
+            awareness: Awareness::Unaware,
+        }
+    }
+
+    /// The enemy the entity can currently see, if any.
+    pub fn target(&self) -> Option<Entity> {
+        match self.awareness {
+            Awareness::Hunting { target, .. } => Some(target),
+            _ => None,
+        }
+    }
+
+    /// Where the entity last saw the enemy it lost track of, if it's still looking.
+    pub fn last_known(&self) -> Option<WorldPoint> {
+        match self.awareness {
+            Awareness::Searching { last_known, .. } => Some(last_known),
+            _ => None,
+        }
+    }
+}
+
+/// Where an entity belongs. Wandering entities stay close to it.
+#[derive(Component, Copy, Clone, Debug)]
+pub struct Home(pub WorldPoint);
+
+/// A route that an entity walks while it has nothing better to do, going from waypoint to
+/// waypoint and starting over once it reaches the last one. Waypoints that can't be reached,
+/// because they're occupied or there's a wall in the way, are skipped.
+#[derive(Component, Clone, Debug)]
+pub struct Patrol {
+    pub waypoints: Vec<WorldPoint>,
+    /// The index of the waypoint the entity is currently heading for.
+    pub next: usize,
+}
+
+impl Patrol {
+    pub fn new(waypoints: Vec<WorldPoint>) -> Self {
+        Patrol { waypoints, next: 0 }
+    }
+}
+
 /// A marker component for the player entity. There is at most one entity with this ID, and its ID
 /// is also stored in the PlayerId resource.
 #[derive(Component, Default, Debug, Copy, Clone)]
 #[storage(NullStorage)]
 pub struct IsPlayer;
 
+/// How two factions feel about each other.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Relation {
+    Hostile,
+    Neutral,
+    Allied,
+}
+
+/// Which side an entity is on. AIs go after entities whose faction is hostile to theirs, and
+/// bumping into an ally swaps places with it rather than attacking it.
+#[derive(Component, Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Faction {
+    /// The player and their allies.
+    Player,
+    /// Monsters that want to kill the player.
+    Monsters,
+    /// Animals that mind their own business unless attacked.
+    Wildlife,
+}
+
+impl Faction {
+    /// How this faction feels about `other`.
+    pub fn relation(self, other: Faction) -> Relation {
+        match (self, other) {
+            (a, b) if a == b => Relation::Allied,
+            (Faction::Player, Faction::Monsters) | (Faction::Monsters, Faction::Player) => {
+                Relation::Hostile
+            }
+            _ => Relation::Neutral,
+        }
+    }
+
+    /// How the factions of `a` and `b` feel about each other. Entities without a faction are
+    /// neutral to everyone.
+    pub fn between(factions: &ReadStorage<Faction>, a: Entity, b: Entity) -> Relation {
+        match (factions.get(a), factions.get(b)) {
+            (Some(a), Some(b)) => a.relation(*b),
+            _ => Relation::Neutral,
+        }
+    }
+}
+
 /// Represents the human-readable name of something. Should be all-lowercase (except for proper
 /// nouns, of course).
 #[derive(Component, Debug, Clone)]
@@ -49,14 +183,87 @@ pub struct Name {
     pub name: String,
 }
 
-/// The position of a given entity inside the world.
-#[derive(Component, Debug, Copy, Clone)]
+/// The position of a given entity inside the world. Changes are flagged, so that MapUpdateSystem
+/// can keep track of where everything is.
+#[derive(Debug, Copy, Clone)]
 pub struct Position(pub WorldPoint);
 
-/// A tag to indicate that an entity cannot be moved through.
+impl Component for Position {
+    type Storage = FlaggedStorage<Self, DenseVecStorage<Self>>;
+}
+
+/// A tag to indicate that an entity cannot be moved through. Like Position, changes are flagged.
+#[derive(Default, Debug, Copy, Clone)]
+pub struct BlocksMovement;
+
+impl Component for BlocksMovement {
+    type Storage = FlaggedStorage<Self, NullStorage<Self>>;
+}
+
+/// A marker component for things that can be picked up and carried around. Items lying on the
+/// floor have a Position; items in an inventory don't.
 #[derive(Component, Default, Debug, Copy, Clone)]
 #[storage(NullStorage)]
-pub struct BlocksMovement;
+pub struct Item;
+
+/// The items that an entity is carrying.
+#[derive(Component, Clone, Debug)]
+pub struct Inventory {
+    pub items: Vec<Entity>,
+    /// The maximum number of items that can be carried at once.
+    pub capacity: usize,
+}
+
+impl Inventory {
+    pub fn new(capacity: usize) -> Self {
+        Inventory {
+            items: vec![],
+            capacity,
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.items.len() >= self.capacity
+    }
+}
+
+/// What happens when a consumable item is used.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ItemEffect {
+    /// Restores hp to the user.
+    Heal { amount: i32 },
+    /// Moves the user to a random unoccupied spot on the map.
+    Teleport,
+    /// Applies a status effect to the user.
+    Status { kind: StatusKind, duration: i32 },
+}
+
+/// An item that's used up when it's used, like a potion or a scroll.
+#[derive(Component, Copy, Clone, Debug)]
+pub struct Consumable {
+    pub effect: ItemEffect,
+}
+
+/// One possible drop in a monster's loot table.
+#[derive(Clone, Debug, Deserialize)]
+pub struct LootDrop {
+    /// The id of the item's blueprint.
+    pub item: String,
+    /// Chance, in percent, that this item is dropped.
+    pub chance: i32,
+}
+
+/// Items that this entity might drop when it dies. Each entry is rolled separately.
+#[derive(Component, Clone, Debug, Default)]
+pub struct LootTable(pub Vec<LootDrop>);
+
+/// Entities with this component are deleted once the player has taken the given number of turns.
+/// Used for things like corpses.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Decay {
+    pub remaining: i32,
+}
 
 /// This entity has some kind of visual representation.
 #[derive(Component, Debug, Clone)]
@@ -73,6 +280,46 @@ pub struct AttackIntent {
     pub target: Entity,
 }
 
+/// Indicates that this entity wants to fire its ranged weapon at the given point. Resolved by
+/// RangedAttackSystem.
+#[derive(Component, Copy, Clone, Debug)]
+pub struct RangedAttackIntent {
+    pub target: WorldPoint,
+}
+
+/// Indicates that this entity wants to pick up the items on its tile. Resolved by
+/// InventorySystem.
+#[derive(Component, Default, Debug, Copy, Clone)]
+#[storage(NullStorage)]
+pub struct PickUpIntent;
+
+/// Indicates that this entity wants to drop an item it's carrying. Resolved by InventorySystem.
+#[derive(Component, Copy, Clone, Debug)]
+pub struct DropIntent {
+    pub item: Entity,
+}
+
+/// Indicates that this entity wants to use a consumable item from its inventory. Resolved by
+/// ConsumableSystem.
+#[derive(Component, Copy, Clone, Debug)]
+pub struct UseItemIntent {
+    pub item: Entity,
+}
+
+/// Indicates that this entity wants to equip an item from its inventory. Resolved by
+/// EquipSystem.
+#[derive(Component, Copy, Clone, Debug)]
+pub struct EquipIntent {
+    pub item: Entity,
+}
+
+/// Indicates that this entity wants to take off whatever it has in the given slot. Resolved by
+/// EquipSystem.
+#[derive(Component, Copy, Clone, Debug)]
+pub struct UnequipIntent {
+    pub slot: EquipSlot,
+}
+
 /// Indicates that this entity wants to move. Resolved by MovementSystem. Only makes sense on entities
 /// that have a Position.
 #[derive(Component, Copy, Clone, Debug)]
@@ -84,20 +331,408 @@ pub struct CombatStats {
     pub max_hp: i32,
     pub hp: i32,
     pub attack: i32,
+    /// Base chance to hit, in percent. The target's evasion is subtracted from this.
+    pub accuracy: i32,
+    /// Subtracted from the attacker's accuracy when this entity is attacked.
+    pub evasion: i32,
+    /// Chance, in percent, that a hit is a critical hit.
+    pub crit_chance: i32,
+    /// The type of damage this entity's attacks deal.
+    pub damage_type: DamageType,
+    /// Subtracted from every instance of physical damage this entity takes.
+    pub defense: i32,
+    /// Subtracted from the chance, in percent, that an enemy notices this entity.
+    pub stealth: i32,
+}
+
+impl CombatStats {
+    /// Restores up to `amount` hp, without going over the maximum. Returns how much was actually
+    /// restored.
+    pub fn heal(&mut self, amount: i32) -> i32 {
+        let healed = amount.min(self.max_hp - self.hp).max(0);
+        self.hp += healed;
+        healed
+    }
+}
+
+/// How many turns regeneration stops for after an entity takes damage.
+pub const REGENERATION_DELAY: i32 = 5;
+
+/// Lets an entity slowly recover hp over time. Handled by RegenerationSystem.
+#[derive(Component, Copy, Clone, Debug)]
+pub struct Regeneration {
+    /// How many turns it takes to recover 1 hp, before equipment is taken into account.
+    pub base_interval: i32,
+    /// How many turns it actually takes to recover 1 hp. Recomputed by StatsSystem.
+    pub interval: i32,
+    /// How many turns have passed since the last hp was recovered.
+    pub progress: i32,
+    /// How many more turns regeneration is stopped for, because the entity was hurt.
+    pub paused: i32,
+}
+
+impl Regeneration {
+    pub fn new(interval: i32) -> Self {
+        Regeneration {
+            base_interval: interval,
+            interval,
+            progress: 0,
+            paused: 0,
+        }
+    }
+
+    /// Stops regeneration for a while, and throws away any progress towards the next hp.
+    pub fn interrupt(&mut self) {
+        self.progress = 0;
+        self.paused = REGENERATION_DELAY;
+    }
+}
+
+/// The stats an entity has before its equipment is taken into account. Entities with this
+/// component have their CombatStats recomputed from it (by StatsSystem) instead of having them
+/// modified directly; only the current hp carries over.
+#[derive(Component, Copy, Clone, Debug, Deserialize)]
+pub struct BaseStats {
+    pub max_hp: i32,
+    pub attack: i32,
+    pub accuracy: i32,
+    pub evasion: i32,
+    pub crit_chance: i32,
+    pub damage_type: DamageType,
+    pub defense: i32,
+    #[serde(default)]
+    pub stealth: i32,
+}
+
+impl BaseStats {
+    /// Computes effective stats from these base stats plus the given bonuses. The resulting hp is
+    /// `hp`, capped to the new maximum. If several bonuses change the damage type, the last one
+    /// wins.
+    pub fn derive<'b>(
+        &self,
+        hp: i32,
+        bonuses: impl IntoIterator<Item = &'b StatBonus>,
+    ) -> CombatStats {
+        let mut stats = CombatStats {
+            max_hp: self.max_hp,
+            hp,
+            attack: self.attack,
+            accuracy: self.accuracy,
+            evasion: self.evasion,
+            crit_chance: self.crit_chance,
+            damage_type: self.damage_type,
+            defense: self.defense,
+            stealth: self.stealth,
+        };
+        for bonus in bonuses {
+            stats.max_hp += bonus.max_hp;
+            stats.attack += bonus.attack;
+            stats.accuracy += bonus.accuracy;
+            stats.evasion += bonus.evasion;
+            stats.crit_chance += bonus.crit_chance;
+            stats.defense += bonus.defense;
+            stats.stealth += bonus.stealth;
+            if let Some(damage_type) = bonus.damage_type {
+                stats.damage_type = damage_type;
+            }
+        }
+        stats.hp = stats.hp.min(stats.max_hp);
+        stats
+    }
+}
+
+/// Modifications to an entity's stats, e.g. from a piece of equipment.
+#[derive(Copy, Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct StatBonus {
+    pub max_hp: i32,
+    pub attack: i32,
+    pub accuracy: i32,
+    pub evasion: i32,
+    pub crit_chance: i32,
+    pub defense: i32,
+    pub stealth: i32,
+    /// Shortens the time it takes to regenerate 1 hp by this many turns.
+    pub regeneration: i32,
+    /// If set, attacks deal this type of damage instead.
+    pub damage_type: Option<DamageType>,
+}
+
+/// Experience that an entity has built up by killing things. Handled by ExperienceSystem, which
+/// levels the entity up once it has enough.
+#[derive(Component, Copy, Clone, Debug)]
+pub struct Experience {
+    pub level: i32,
+    /// Experience gained since the last level-up.
+    pub xp: i32,
+}
+
+impl Default for Experience {
+    fn default() -> Self {
+        Experience { level: 1, xp: 0 }
+    }
+}
+
+impl Experience {
+    /// How much experience is needed to go from the current level to the next one.
+    pub fn xp_needed(&self) -> i32 {
+        20 * self.level
+    }
+}
+
+/// How much experience killing this entity is worth.
+#[derive(Component, Copy, Clone, Debug)]
+pub struct ExperienceValue(pub i32);
+
+/// The entity that most recently damaged this one. Whoever dealt the killing blow gets the
+/// experience.
+#[derive(Component, Copy, Clone, Debug)]
+pub struct LastAttacker(pub Entity);
+
+/// The perks an entity has picked, and the choices it's been offered but hasn't picked from yet.
+/// Only entities with this component get offered perks when they level up.
+#[derive(Component, Clone, Debug, Default)]
+pub struct Perks {
+    /// Ids of the perks that have been picked.
+    pub taken: Vec<String>,
+    /// Sets of perk ids to pick from, oldest first. One perk gets picked from each set.
+    pub offers: Vec<Vec<String>>,
+}
+
+/// Heals the entity for this percentage of the damage it deals.
+#[derive(Component, Copy, Clone, Debug)]
+pub struct Lifesteal(pub i32);
+
+/// The places where an entity can wear or wield equipment.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EquipSlot {
+    Weapon,
+    Armor,
+    Ring,
+    Amulet,
+}
+
+impl EquipSlot {
+    pub const ALL: [EquipSlot; 4] = [
+        EquipSlot::Weapon,
+        EquipSlot::Armor,
+        EquipSlot::Ring,
+        EquipSlot::Amulet,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EquipSlot::Weapon => "weapon",
+            EquipSlot::Armor => "armor",
+            EquipSlot::Ring => "ring",
+            EquipSlot::Amulet => "amulet",
+        }
+    }
+}
+
+/// An item that can be worn or wielded.
+#[derive(Component, Copy, Clone, Debug, Deserialize)]
+pub struct Equippable {
+    pub slot: EquipSlot,
+    pub bonus: StatBonus,
+}
+
+/// What an entity is currently wearing or wielding. Equipped items aren't in the entity's
+/// Inventory.
+#[derive(Component, Clone, Debug, Default)]
+pub struct Equipment(pub HashMap<EquipSlot, Entity>);
+
+/// Lets an entity attack from a distance. Ranged attacks otherwise use the entity's CombatStats,
+/// just like melee attacks.
+#[derive(Component, Copy, Clone, Debug, Deserialize)]
+pub struct RangedWeapon {
+    /// The maximum number of tiles a projectile travels.
+    pub range: i32,
+    /// Remaining ammunition. Each shot uses up one.
+    pub ammo: i32,
+}
+
+/// The different kinds of damage that can be dealt.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DamageType {
+    Physical,
+    Fire,
+    Cold,
+    Poison,
+}
+
+impl DamageType {
+    /// The name of the damage type, as in "4 fire damage".
+    pub fn name(&self) -> &'static str {
+        match self {
+            DamageType::Physical => "physical",
+            DamageType::Fire => "fire",
+            DamageType::Cold => "cold",
+            DamageType::Poison => "poison",
+        }
+    }
+
+    /// The verb used to describe dealing this kind of damage, as in "the swarmer burns you".
+    pub fn verb(&self) -> &'static str {
+        match self {
+            DamageType::Physical => "hits",
+            DamageType::Fire => "burns",
+            DamageType::Cold => "freezes",
+            DamageType::Poison => "poisons",
+        }
+    }
+}
+
+/// How an entity reacts to a specific type of damage.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Resistance {
+    /// Takes half damage.
+    Resistant,
+    /// Takes double damage.
+    Weak,
+    /// Takes no damage at all.
+    Immune,
+}
+
+impl Resistance {
+    pub fn apply(&self, amount: i32) -> i32 {
+        match self {
+            Resistance::Resistant => amount / 2,
+            Resistance::Weak => amount * 2,
+            Resistance::Immune => 0,
+        }
+    }
+}
+
+/// Per-damage-type resistances, weaknesses and immunities. Damage types that aren't in the map
+/// are applied as-is.
+#[derive(Component, Clone, Debug, Default)]
+pub struct Resistances(pub HashMap<DamageType, Resistance>);
+
+impl Resistances {
+    /// Returns how much damage an attack of the given type and amount actually deals.
+    pub fn modify(&self, kind: DamageType, amount: i32) -> i32 {
+        self.0
+            .get(&kind)
+            .map_or(amount, |resistance| resistance.apply(amount))
+    }
+}
+
+/// A single instance of damage that's waiting to be applied.
+#[derive(Copy, Clone, Debug)]
+pub struct Damage {
+    pub amount: i32,
+    pub kind: DamageType,
+    /// The entity responsible for the damage.
+    pub source: Entity,
+    pub critical: bool,
 }
 
 /// Indicates that damage is going to be applied to the given entity this tick.
 #[derive(Component, Clone, Debug)]
-pub struct QueuedDamage(pub Vec<i32>);
+pub struct QueuedDamage(pub Vec<Damage>);
 
 impl QueuedDamage {
-    pub fn add(store: &mut WriteStorage<Self>, who: Entity, amount: i32) {
+    pub fn add(store: &mut WriteStorage<Self>, who: Entity, damage: Damage) {
         if let Some(queue) = store.get_mut(who) {
-            queue.0.push(amount);
+            queue.0.push(damage);
         } else {
             store
-                .insert(who, QueuedDamage(vec![amount]))
+                .insert(who, QueuedDamage(vec![damage]))
                 .expect("could not enqueue damage");
         }
     }
 }
+
+/// The kinds of status effects that can be applied to an entity.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StatusKind {
+    /// Deals poison damage every turn.
+    Poison { damage: i32 },
+    /// Deals physical damage every turn.
+    Bleed { damage: i32 },
+    /// The entity loses its turns.
+    Stun,
+    /// Lowers the entity's initiative by the given amount, so that it acts more often.
+    Haste { amount: i32 },
+}
+
+impl StatusKind {
+    /// Describes an entity under this effect, as in "the swarmer is poisoned".
+    pub fn adjective(&self) -> &'static str {
+        match self {
+            StatusKind::Poison { .. } => "poisoned",
+            StatusKind::Bleed { .. } => "bleeding",
+            StatusKind::Stun => "stunned",
+            StatusKind::Haste { .. } => "hasted",
+        }
+    }
+}
+
+/// A status effect that's currently active on an entity.
+#[derive(Copy, Clone, Debug)]
+pub struct StatusEffect {
+    pub kind: StatusKind,
+    /// How many more of the affected entity's turns this lasts for.
+    pub remaining: i32,
+    /// The entity that applied this effect. Damage dealt by the effect is attributed to it.
+    pub source: Entity,
+}
+
+/// All of the status effects active on an entity.
+#[derive(Component, Clone, Debug, Default)]
+pub struct StatusEffects(pub Vec<StatusEffect>);
+
+impl StatusEffects {
+    /// Applies the effect to the given entity. If it already has an effect of the same kind, that
+    /// one is replaced, but the longer of the two durations is kept.
+    pub fn apply(store: &mut WriteStorage<Self>, who: Entity, effect: StatusEffect) {
+        if let Some(effects) = store.get_mut(who) {
+            let same_kind = effects.0.iter_mut().find(|existing| {
+                std::mem::discriminant(&existing.kind) == std::mem::discriminant(&effect.kind)
+            });
+            if let Some(existing) = same_kind {
+                let remaining = existing.remaining.max(effect.remaining);
+                *existing = StatusEffect {
+                    remaining,
+                    ..effect
+                };
+            } else {
+                effects.0.push(effect);
+            }
+        } else {
+            store
+                .insert(who, StatusEffects(vec![effect]))
+                .expect("could not apply status effect");
+        }
+    }
+
+    pub fn is_stunned(&self) -> bool {
+        self.0.iter().any(|effect| effect.kind == StatusKind::Stun)
+    }
+
+    /// The total amount by which haste effects lower this entity's initiative.
+    pub fn haste(&self) -> i32 {
+        self.0
+            .iter()
+            .map(|effect| match effect.kind {
+                StatusKind::Haste { amount } => amount,
+                _ => 0,
+            })
+            .sum()
+    }
+}
+
+/// Gives this entity's successful attacks a chance to inflict a status effect on the target.
+#[derive(Component, Copy, Clone, Debug, Deserialize)]
+pub struct InflictsStatus {
+    pub kind: StatusKind,
+    /// How many turns the effect lasts for.
+    pub duration: i32,
+    /// Chance, in percent, that a hit inflicts the effect.
+    pub chance: i32,
+}
@@ -1,49 +1,153 @@
 use log::info;
+use rand::SeedableRng;
+use rand_pcg::Pcg32;
 use specs::prelude::*;
 
+use crate::abilities::{Abilities, Ability, AbilityIntent, Energy};
 use crate::ai;
 use crate::components::*;
+use crate::event_log::{Event, EventLog};
 use crate::geometry::*;
 use crate::map::Map;
+use crate::pack::{PackMember, Packs};
+use crate::perks::PerkPool;
 use crate::systems::*;
 
 #[derive(Debug, Copy, Clone)]
 pub struct PlayerId(pub Entity);
 
+/// The random number generator for the current run. All randomness in the game logic should go
+/// through this, so that a run can be replayed exactly given its seed.
+#[derive(Debug, Clone)]
+pub struct GameRng(pub Pcg32);
+
+/// Paths of projectiles fired since the frontend last took them, so that it can draw them.
+#[derive(Debug, Default)]
+pub struct Projectiles(pub Vec<Vec<WorldPoint>>);
+
+/// Tiles hit by area effects since the frontend last took them, so that it can highlight them.
+#[derive(Debug, Default)]
+pub struct HighlightedTiles(pub Vec<WorldPoint>);
+
+/// A noise that an entity made, which wakes up or alerts enemies that hear it.
+#[derive(Debug, Copy, Clone)]
+pub struct Noise {
+    pub source: Entity,
+    pub origin: WorldPoint,
+    /// How many tiles the noise carries.
+    pub radius: i32,
+}
+
+/// Noises made since NoiseSystem last ran.
+#[derive(Debug, Default)]
+pub struct Noises(pub Vec<Noise>);
+
 #[derive(Debug, Clone)]
 pub enum Action {
-    Move { motion: WorldVector },
-    Attack { target: Entity },
+    Move {
+        motion: WorldVector,
+    },
+    Attack {
+        target: Entity,
+    },
+    RangedAttack {
+        target: WorldPoint,
+    },
+    /// Use the ability at the given index of the entity's Abilities.
+    UseAbility {
+        ability: usize,
+        target: WorldPoint,
+    },
+    /// Pick up the items on the entity's tile.
+    PickUp,
+    Drop {
+        item: Entity,
+    },
+    UseItem {
+        item: Entity,
+    },
+    Equip {
+        item: Entity,
+    },
+    Unequip {
+        slot: EquipSlot,
+    },
+    /// Do nothing this turn.
+    Wait,
 }
 
 pub struct Engine {
     pub world: World,
+    map_update: MapUpdateSystem,
 }
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum LoopState {
     Looping,
     WaitingForPlayer,
+    /// The player has levelled up and has to pick a perk before anything else can happen.
+    ChoosingPerk,
     GameOver,
 }
 
 impl Engine {
-    pub fn new() -> Self {
+    pub fn new(seed: u64) -> Self {
+        info!("starting run with seed {}", seed);
         let mut world = World::new();
         world.register::<Position>();
         world.register::<BlocksMovement>();
         world.register::<Visible>();
+        world.register::<Decay>();
         specs::RunNow::setup(&mut InitiativeSystem, &mut world);
+        specs::RunNow::setup(&mut StatusEffectSystem, &mut world);
+        let mut map_update = MapUpdateSystem::default();
+        specs::RunNow::setup(&mut map_update, &mut world);
         world.register::<Ready>();
         world.register::<ai::AIComponent>();
         world.register::<Name>();
         world.register::<MoveIntent>();
         world.register::<AttackIntent>();
+        world.register::<RangedAttackIntent>();
+        world.register::<RangedWeapon>();
+        world.register::<Item>();
+        world.register::<Inventory>();
+        world.register::<LootTable>();
+        world.register::<PickUpIntent>();
+        world.register::<DropIntent>();
+        world.register::<Consumable>();
+        world.register::<UseItemIntent>();
+        world.register::<Equippable>();
+        world.register::<Equipment>();
+        world.register::<EquipIntent>();
+        world.register::<UnequipIntent>();
+        world.register::<BaseStats>();
+        world.register::<Experience>();
+        world.register::<ExperienceValue>();
+        world.register::<LastAttacker>();
+        world.register::<Perks>();
+        world.register::<Lifesteal>();
+        world.register::<Regeneration>();
+        world.register::<Faction>();
+        world.register::<Perception>();
+        world.register::<Home>();
+        world.register::<Patrol>();
+        world.register::<PackMember>();
+        world.register::<Abilities>();
+        world.register::<Energy>();
+        world.register::<AbilityIntent>();
         world.register::<QueuedDamage>();
         world.register::<CombatStats>();
+        world.register::<Resistances>();
+        world.register::<InflictsStatus>();
         world.register::<IsPlayer>();
         world.insert(LoopState::Looping);
-        Engine { world }
+        world.insert(GameRng(Pcg32::seed_from_u64(seed)));
+        world.insert(Projectiles::default());
+        world.insert(HighlightedTiles::default());
+        world.insert(PerkPool::load());
+        world.insert(Packs::default());
+        world.insert(Noises::default());
+        Engine { world, map_update }
     }
 
     pub fn set_action(&mut self, action: Action) {
@@ -68,20 +172,294 @@ impl Engine {
                     .unwrap()
                     .0;
                 let target = player_pos + motion;
-                if map.is_blocked(target) {
-                    if let Some(blocker) = map.blockers(target) {
-                        Some(Action::Attack { target: blocker })
-                    } else {
-                        None
-                    }
+                if !map.contains(target) {
+                    None
+                } else if map.is_blocked(target) {
+                    // Bumping into an ally swaps places with it; bumping into anything else
+                    // attacks it.
+                    let factions = self.world.read_storage::<Faction>();
+                    map.blockers(target).map(|blocker| {
+                        match Faction::between(&factions, player_id, blocker) {
+                            Relation::Allied => action,
+                            _ => Action::Attack { target: blocker },
+                        }
+                    })
                 } else {
                     Some(action)
                 }
             }
+            Action::RangedAttack { target } => {
+                let player_id = self.world.fetch::<PlayerId>().0;
+                let player_pos = self
+                    .world
+                    .read_storage::<Position>()
+                    .get(player_id)
+                    .unwrap()
+                    .0;
+                let weapons = self.world.read_storage::<RangedWeapon>();
+                let weapon = weapons.get(player_id)?;
+                if weapon.ammo > 0
+                    && target != player_pos
+                    && distance(player_pos, target) <= weapon.range
+                {
+                    Some(action)
+                } else {
+                    None
+                }
+            }
+            Action::UseAbility { ability, target } => {
+                let player_id = self.world.fetch::<PlayerId>().0;
+                let player_pos = self.player_position()?;
+                let abilities = self.world.read_storage::<Abilities>();
+                let known = abilities.get(player_id)?.0.get(ability)?;
+                let energy = self.world.read_storage::<Energy>();
+                let map = self.world.fetch::<Map>();
+                if known.cooldown == 0
+                    && energy.get(player_id)?.current >= known.ability.cost
+                    && known.ability.can_target(&map, player_pos, target)
+                {
+                    Some(action)
+                } else {
+                    None
+                }
+            }
+            Action::PickUp => {
+                let player_pos = self.player_position()?;
+                let map = self.world.fetch::<Map>();
+                let items = self.world.read_storage::<Item>();
+                map.entities_at(player_pos)
+                    .iter()
+                    .any(|entity| items.contains(*entity))
+                    .then_some(action)
+            }
+            Action::Drop { item } => {
+                let player_id = self.world.fetch::<PlayerId>().0;
+                let inventories = self.world.read_storage::<Inventory>();
+                inventories
+                    .get(player_id)?
+                    .items
+                    .contains(&item)
+                    .then_some(action)
+            }
+            Action::UseItem { item } => {
+                let player_id = self.world.fetch::<PlayerId>().0;
+                let inventories = self.world.read_storage::<Inventory>();
+                let consumables = self.world.read_storage::<Consumable>();
+                (inventories.get(player_id)?.items.contains(&item) && consumables.contains(item))
+                    .then_some(action)
+            }
+            Action::Equip { item } => {
+                let player_id = self.world.fetch::<PlayerId>().0;
+                let inventories = self.world.read_storage::<Inventory>();
+                let equippables = self.world.read_storage::<Equippable>();
+                (inventories.get(player_id)?.items.contains(&item) && equippables.contains(item))
+                    .then_some(action)
+            }
+            Action::Unequip { slot } => {
+                let player_id = self.world.fetch::<PlayerId>().0;
+                let inventories = self.world.read_storage::<Inventory>();
+                let equipment = self.world.read_storage::<Equipment>();
+                (equipment.get(player_id)?.0.contains_key(&slot)
+                    && !inventories.get(player_id)?.is_full())
+                .then_some(action)
+            }
             _ => Some(action),
         }
     }
 
+    /// What the player has in each equipment slot, by name.
+    pub fn player_equipment(&self) -> Vec<(EquipSlot, Option<String>)> {
+        let player_id = self.world.fetch::<PlayerId>().0;
+        let equipment = self.world.read_storage::<Equipment>();
+        let names = self.world.read_storage::<Name>();
+        let equipment = equipment.get(player_id);
+        EquipSlot::ALL
+            .iter()
+            .map(|slot| {
+                let item = equipment.and_then(|equipment| equipment.0.get(slot));
+                let name = item.map(|item| {
+                    names
+                        .get(*item)
+                        .map_or_else(|| "something".to_string(), |name| name.name.clone())
+                });
+                (*slot, name)
+            })
+            .collect()
+    }
+
+    pub fn player_experience(&self) -> Option<Experience> {
+        let player_id = self.world.fetch::<PlayerId>().0;
+        self.world
+            .read_storage::<Experience>()
+            .get(player_id)
+            .copied()
+    }
+
+    /// The names and descriptions of the perks that the player currently has to pick from.
+    pub fn perk_offer(&self) -> Vec<(String, String)> {
+        let player_id = self.world.fetch::<PlayerId>().0;
+        let pool = self.world.fetch::<PerkPool>();
+        let perks = self.world.read_storage::<Perks>();
+        perks
+            .get(player_id)
+            .and_then(|perks| perks.offers.first())
+            .map_or_else(Vec::new, |offer| {
+                offer
+                    .iter()
+                    .map(|id| (pool.0[id].name.clone(), pool.0[id].description.clone()))
+                    .collect()
+            })
+    }
+
+    /// Picks the perk at `index` of the player's current offer. Once there are no offers left, the
+    /// game carries on. Returns false if there's no such perk.
+    pub fn choose_perk(&mut self, index: usize) -> bool {
+        let player_id = self.world.fetch::<PlayerId>().0;
+        let id = {
+            let mut perks = self.world.write_storage::<Perks>();
+            let perks = match perks.get_mut(player_id) {
+                Some(perks) => perks,
+                None => return false,
+            };
+            let id = match perks.offers.first().and_then(|offer| offer.get(index)) {
+                Some(id) => id.clone(),
+                None => return false,
+            };
+            perks.offers.remove(0);
+            perks.taken.push(id.clone());
+            if perks.offers.is_empty() {
+                *self.world.fetch_mut::<LoopState>() = LoopState::Looping;
+            }
+            id
+        };
+        let pool = self.world.fetch::<PerkPool>();
+        let perk = &pool.0[&id];
+        perk.apply(&self.world, player_id);
+        self.world.fetch_mut::<EventLog>().log(
+            Event::PerkChosen {
+                who: player_id,
+                name: perk.name.clone(),
+            }
+            .format(&self.world.read_storage::<Name>()),
+        );
+        true
+    }
+
+    /// A description of what each entity with Perception currently knows, for debugging.
+    pub fn perception_report(&self) -> Vec<String> {
+        let perception = self.world.read_storage::<Perception>();
+        let positions = self.world.read_storage::<Position>();
+        let names = self.world.read_storage::<Name>();
+        let name = |entity: Entity| {
+            names
+                .get(entity)
+                .map_or_else(|| format!("{:?}", entity), |name| name.name.clone())
+        };
+        (&self.world.entities(), &perception, &positions)
+            .join()
+            .map(|(entity, perception, position)| {
+                let state = match perception.awareness {
+                    Awareness::Asleep => "asleep".to_string(),
+                    Awareness::Unaware => "unaware".to_string(),
+                    Awareness::Hunting { target, .. } => format!("hunting {}", name(target)),
+                    Awareness::Searching {
+                        last_known,
+                        turns_left,
+                    } => format!(
+                        "searching around ({}, {}), {} turns left",
+                        last_known.x, last_known.y, turns_left
+                    ),
+                };
+                format!(
+                    "{} at ({}, {}): {}",
+                    name(entity),
+                    position.0.x,
+                    position.0.y,
+                    state
+                )
+            })
+            .collect()
+    }
+
+    /// The names of the perks that the player has picked.
+    pub fn player_perks(&self) -> Vec<String> {
+        let player_id = self.world.fetch::<PlayerId>().0;
+        let pool = self.world.fetch::<PerkPool>();
+        let perks = self.world.read_storage::<Perks>();
+        perks.get(player_id).map_or_else(Vec::new, |perks| {
+            perks
+                .taken
+                .iter()
+                .map(|id| pool.0[id].name.clone())
+                .collect()
+        })
+    }
+
+    pub fn player_stats(&self) -> Option<CombatStats> {
+        let player_id = self.world.fetch::<PlayerId>().0;
+        self.world
+            .read_storage::<CombatStats>()
+            .get(player_id)
+            .copied()
+    }
+
+    /// The items the player is carrying, along with their names.
+    pub fn player_inventory(&self) -> Vec<(Entity, String)> {
+        let player_id = self.world.fetch::<PlayerId>().0;
+        let inventories = self.world.read_storage::<Inventory>();
+        let names = self.world.read_storage::<Name>();
+        inventories
+            .get(player_id)
+            .map_or_else(Vec::new, |inventory| {
+                inventory
+                    .items
+                    .iter()
+                    .map(|item| {
+                        let name = names.get(*item).map_or("something", |name| &name.name);
+                        (*item, name.to_string())
+                    })
+                    .collect()
+            })
+    }
+
+    pub fn player_position(&self) -> Option<WorldPoint> {
+        let player_id = self.world.fetch::<PlayerId>().0;
+        self.world
+            .read_storage::<Position>()
+            .get(player_id)
+            .map(|pos| pos.0)
+    }
+
+    /// The ability bound to the given slot of the player's ability list, if any.
+    pub fn player_ability(&self, index: usize) -> Option<Ability> {
+        let player_id = self.world.fetch::<PlayerId>().0;
+        self.world
+            .read_storage::<Abilities>()
+            .get(player_id)?
+            .0
+            .get(index)
+            .map(|known| known.ability)
+    }
+
+    /// Finds the closest entity (other than the player and their allies) that can be attacked,
+    /// and returns its position. Used to pick a default target for ranged attacks.
+    pub fn nearest_target(&self) -> Option<WorldPoint> {
+        let player_id = self.world.fetch::<PlayerId>().0;
+        let positions = self.world.read_storage::<Position>();
+        let player_pos = positions.get(player_id)?.0;
+        let stats = self.world.read_storage::<CombatStats>();
+        let factions = self.world.read_storage::<Faction>();
+        let entities = self.world.entities();
+        (&entities, &positions, &stats)
+            .join()
+            .filter(|(entity, _, _)| {
+                *entity != player_id
+                    && Faction::between(&factions, player_id, *entity) != Relation::Allied
+            })
+            .map(|(_, pos, _)| pos.0)
+            .min_by_key(|pos| distance(player_pos, *pos))
+    }
+
     pub fn perform(&mut self, entity: Entity, action: Action) {
         info!("{:?} performing {:?}", entity, action);
         if entity == self.world.fetch::<PlayerId>().0 {
@@ -104,6 +482,49 @@ impl Engine {
                     .insert(entity, AttackIntent { target })
                     .expect("failed to insert attack intent");
             }
+            Action::RangedAttack { target } => {
+                self.world
+                    .write_storage::<RangedAttackIntent>()
+                    .insert(entity, RangedAttackIntent { target })
+                    .expect("failed to insert ranged attack intent");
+            }
+            Action::UseAbility { ability, target } => {
+                self.world
+                    .write_storage::<AbilityIntent>()
+                    .insert(entity, AbilityIntent { ability, target })
+                    .expect("failed to insert ability intent");
+            }
+            Action::PickUp => {
+                self.world
+                    .write_storage::<PickUpIntent>()
+                    .insert(entity, PickUpIntent)
+                    .expect("failed to insert pick up intent");
+            }
+            Action::Drop { item } => {
+                self.world
+                    .write_storage::<DropIntent>()
+                    .insert(entity, DropIntent { item })
+                    .expect("failed to insert drop intent");
+            }
+            Action::UseItem { item } => {
+                self.world
+                    .write_storage::<UseItemIntent>()
+                    .insert(entity, UseItemIntent { item })
+                    .expect("failed to insert use item intent");
+            }
+            Action::Equip { item } => {
+                self.world
+                    .write_storage::<EquipIntent>()
+                    .insert(entity, EquipIntent { item })
+                    .expect("failed to insert equip intent");
+            }
+            Action::Unequip { slot } => {
+                self.world
+                    .write_storage::<UnequipIntent>()
+                    .insert(entity, UnequipIntent { slot })
+                    .expect("failed to insert unequip intent");
+            }
+            Action::Wait => (),
         }
     }
 
@@ -137,17 +558,32 @@ impl Engine {
     pub fn tick(&mut self) {
         // We run this in a loop so that we're not stuck ticking once per frame. TODO: Move the
         // tick-until-waiting into a separate function or something.
+        if *self.world.fetch::<LoopState>() == LoopState::ChoosingPerk {
+            return;
+        }
         loop {
             InitiativeSystem.run_now(&self.world);
+            StatusEffectSystem.run_now(&self.world);
+            CooldownSystem.run_now(&self.world);
+            RegenerationSystem.run_now(&self.world);
+            DecaySystem.run_now(&self.world);
+            PerceptionSystem.run_now(&self.world);
             if let Some((entity, action)) = self.find_actor() {
                 self.world.write_storage::<Ready>().remove(entity);
                 self.perform(entity, action);
             }
             MovementSystem.run_now(&self.world);
-            MapUpdateSystem.run_now(&self.world);
+            self.map_update.run_now(&self.world);
+            RangedAttackSystem.run_now(&self.world);
+            AbilitySystem.run_now(&self.world);
+            InventorySystem.run_now(&self.world);
+            EquipSystem.run_now(&self.world);
+            ConsumableSystem.run_now(&self.world);
+            StatsSystem.run_now(&self.world);
             AttackSystem.run_now(&self.world);
             DamageSystem.run_now(&self.world);
             DeathSystem.run_now(&self.world);
+            ExperienceSystem.run_now(&self.world);
             self.world.maintain();
             if *self.world.fetch_mut::<LoopState>() != LoopState::Looping {
                 return;
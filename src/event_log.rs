@@ -1,4 +1,4 @@
-use crate::components::Name;
+use crate::components::{DamageType, Name, StatusKind};
 use log::*;
 use quicksilver::{
     geom::Rectangle,
@@ -15,11 +15,102 @@ pub enum Event {
         from: Entity,
         to: Entity,
         amount: i32,
+        kind: DamageType,
+    },
+    Critical {
+        from: Entity,
+        to: Entity,
+        amount: i32,
+        kind: DamageType,
+    },
+    Miss {
+        from: Entity,
+        to: Entity,
     },
     Death {
         who: Entity,
     },
-    Other(String),
+    OutOfAmmo {
+        who: Entity,
+    },
+    PickUp {
+        who: Entity,
+        item: Entity,
+    },
+    Drop {
+        who: Entity,
+        item: Entity,
+    },
+    InventoryFull {
+        who: Entity,
+    },
+    /// A monster dropped a freshly created item when it died. The item is given by name, since
+    /// it won't have been fully created yet.
+    Loot {
+        who: Entity,
+        item: String,
+    },
+    UseItem {
+        who: Entity,
+        item: Entity,
+    },
+    Teleport {
+        who: Entity,
+    },
+    Equip {
+        who: Entity,
+        item: Entity,
+    },
+    Unequip {
+        who: Entity,
+        item: Entity,
+    },
+    AbilityUsed {
+        who: Entity,
+        name: &'static str,
+    },
+    Heal {
+        who: Entity,
+        amount: i32,
+    },
+    StatusApplied {
+        who: Entity,
+        kind: StatusKind,
+    },
+    StatusExpired {
+        who: Entity,
+        kind: StatusKind,
+    },
+    LevelUp {
+        who: Entity,
+        level: i32,
+        /// The player gets a message of their own.
+        is_player: bool,
+    },
+    PerkChosen {
+        who: Entity,
+        name: String,
+    },
+    /// A monster lost its nerve and started running away.
+    Flee {
+        who: Entity,
+    },
+    /// A fleeing monster had nowhere left to run.
+    Cornered {
+        who: Entity,
+    },
+    /// A pack member spotted an enemy and called the rest of its pack.
+    Alert {
+        who: Entity,
+    },
+    WakeUp {
+        who: Entity,
+    },
+    /// An attack on a sleeping entity.
+    SneakAttack {
+        from: Entity,
+        to: Entity,
+    },
 }
 
 impl Event {
@@ -30,14 +121,76 @@ impl Event {
                 .map_or_else(|| "an unnamed bug".to_string(), |name| name.name.clone())
         };
         match self {
-            Event::Damage { from, to, amount } => format!(
-                "{} hits {} for {} damage.",
+            Event::Damage {
+                from,
+                to,
+                amount,
+                kind,
+            } => format!(
+                "{} {} {} for {} {} damage.",
+                lookup(from),
+                kind.verb(),
+                lookup(to),
+                amount,
+                kind.name()
+            ),
+            Event::Critical {
+                from,
+                to,
+                amount,
+                kind,
+            } => format!(
+                "{} critically {} {} for {} {} damage!",
                 lookup(from),
+                kind.verb(),
                 lookup(to),
-                amount
+                amount,
+                kind.name()
             ),
+            Event::Miss { from, to } => format!("{} misses {}.", lookup(from), lookup(to)),
             Event::Death { who } => format!("{} dies.", lookup(who)),
-            Event::Other(message) => message.clone(),
+            Event::OutOfAmmo { who } => format!("{} is out of ammunition.", lookup(who)),
+            Event::PickUp { who, item } => format!("{} picks up {}.", lookup(who), lookup(item)),
+            Event::Drop { who, item } => format!("{} drops {}.", lookup(who), lookup(item)),
+            Event::Loot { who, item } => format!("{} drops {}.", lookup(who), item),
+            Event::InventoryFull { who } => {
+                format!("{} can't carry any more.", lookup(who))
+            }
+            Event::UseItem { who, item } => format!("{} uses {}.", lookup(who), lookup(item)),
+            Event::Teleport { who } => format!("{} vanishes!", lookup(who)),
+            Event::Equip { who, item } => format!("{} equips {}.", lookup(who), lookup(item)),
+            Event::Unequip { who, item } => {
+                format!("{} takes off {}.", lookup(who), lookup(item))
+            }
+            Event::AbilityUsed { who, name } => format!("{} uses {}.", lookup(who), name),
+            Event::Heal { who, amount } => {
+                format!("{} recovers {} hit points.", lookup(who), amount)
+            }
+            Event::StatusApplied { who, kind } => {
+                format!("{} is {}.", lookup(who), kind.adjective())
+            }
+            Event::StatusExpired { who, kind } => {
+                format!("{} is no longer {}.", lookup(who), kind.adjective())
+            }
+            Event::LevelUp {
+                who,
+                level,
+                is_player,
+            } => {
+                if *is_player {
+                    "You feel more experienced!".to_string()
+                } else {
+                    format!("{} looks more experienced (level {}).", lookup(who), level)
+                }
+            }
+            Event::PerkChosen { who, name } => format!("{} gains {}.", lookup(who), name),
+            Event::Flee { who } => format!("{} flees!", lookup(who)),
+            Event::Cornered { who } => format!("{} is cornered and turns to fight!", lookup(who)),
+            Event::Alert { who } => format!("{} calls out to its pack!", lookup(who)),
+            Event::WakeUp { who } => format!("{} wakes up!", lookup(who)),
+            Event::SneakAttack { from, to } => {
+                format!("{} catches {} off guard!", lookup(from), lookup(to))
+            }
         }
     }
 }
@@ -61,6 +214,9 @@ impl EventLog {
     pub fn log(&mut self, event: String) {
         info!("Logging event: {:?}", event);
         self.events.push_back(event);
+        while self.events.len() > self.capacity {
+            self.events.pop_front();
+        }
     }
 
     /// Returns the events, from *newest to oldest*. This is the order that they should be rendered
@@ -7,3 +7,86 @@ pub struct WorldSpace;
 
 pub type WorldPoint = euclid::Point2D<i32, WorldSpace>;
 pub type WorldVector = euclid::Vector2D<i32, WorldSpace>;
+
+/// The distance between two points, counting diagonal steps as 1 (since entities can move
+/// diagonally).
+pub fn distance(from: WorldPoint, to: WorldPoint) -> i32 {
+    let delta = to - from;
+    delta.x.abs().max(delta.y.abs())
+}
+
+/// Returns the points on the straight line from `from` to `to`, as computed by Bresenham's line
+/// algorithm. The result does not include `from` but does include `to`.
+pub fn line(from: WorldPoint, to: WorldPoint) -> Vec<WorldPoint> {
+    let delta = to - from;
+    let (dx, dy) = (delta.x.abs(), -delta.y.abs());
+    let (step_x, step_y) = (delta.x.signum(), delta.y.signum());
+    let mut error = dx + dy;
+    let mut current = from;
+    let mut points = vec![];
+    while current != to {
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            current.x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            current.y += step_y;
+        }
+        points.push(current);
+    }
+    points
+}
+
+/// All points that are at most `radius` away from `center`, as measured by `distance`.
+pub fn points_within(center: WorldPoint, radius: i32) -> Vec<WorldPoint> {
+    let mut points = vec![];
+    for y in -radius..=radius {
+        for x in -radius..=radius {
+            points.push(center + WorldVector::new(x, y));
+        }
+    }
+    points
+}
+
+/// Whether `point` lies in the cone that starts at `origin`, points towards `toward`, and is
+/// `2 * half_angle` radians wide. The origin itself is not part of the cone.
+pub fn in_cone(origin: WorldPoint, toward: WorldPoint, point: WorldPoint, half_angle: f32) -> bool {
+    let direction = (toward - origin).to_f32();
+    let offset = (point - origin).to_f32();
+    if offset.square_length() == 0.0 || direction.square_length() == 0.0 {
+        return false;
+    }
+    offset.angle_to(direction).radians.abs() <= half_angle
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_steps_from_one_end_to_the_other() {
+        let from = WorldPoint::new(0, 0);
+        assert_eq!(line(from, from), vec![]);
+        assert_eq!(
+            line(from, WorldPoint::new(4, 2)),
+            vec![
+                WorldPoint::new(1, 1),
+                WorldPoint::new(2, 1),
+                WorldPoint::new(3, 2),
+                WorldPoint::new(4, 2),
+            ]
+        );
+        for to in points_within(from, 5) {
+            let points = line(from, to);
+            assert_eq!(points.len() as i32, distance(from, to));
+            let mut previous = from;
+            for point in points {
+                assert_eq!(distance(previous, point), 1);
+                previous = point;
+            }
+            assert_eq!(previous, to);
+        }
+    }
+}
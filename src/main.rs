@@ -1,20 +1,30 @@
+mod abilities;
 mod ai;
+mod behavior;
+mod blueprints;
 mod components;
 mod engine;
 mod event_log;
 mod geometry;
 mod map;
+mod overlay;
+mod pack;
+mod perks;
 mod systems;
 mod tiles;
 
-use crate::ai::{AIComponent, PlayerAI, Swarm};
+use crate::abilities::{Abilities, Energy, Targeting};
+use crate::ai::{AIComponent, PlayerAI};
 use crate::components::*;
 use crate::engine::*;
 use crate::event_log::EventLogRenderer;
+use crate::geometry::{WorldPoint, WorldVector};
+use crate::map::TileType;
+use crate::overlay::OverlayRenderer;
 use crate::tiles::*;
 use quicksilver::{
     geom::{Rectangle, Vector},
-    graphics::{Color, Graphics, VectorFont},
+    graphics::{Color, FontRenderer, Graphics, VectorFont},
     input::{Event, Input, Key},
     Result, Settings, Window,
 };
@@ -22,8 +32,65 @@ use specs::prelude::*;
 
 const WIDTH: i32 = 80;
 const HEIGHT: i32 = 40;
+/// How many rows of the screen the map takes up. The event log fills the rest.
 const MAP_HEIGHT: i32 = 30;
 const TILE_SIZE: i32 = 16;
+/// How many frames a projectile's path stays on screen after it's fired.
+const PROJECTILE_FRAMES: u32 = 10;
+
+/// Keys used to pick an entry out of a menu, in order.
+const LETTER_KEYS: [Key; 26] = [
+    Key::A,
+    Key::B,
+    Key::C,
+    Key::D,
+    Key::E,
+    Key::F,
+    Key::G,
+    Key::H,
+    Key::I,
+    Key::J,
+    Key::K,
+    Key::L,
+    Key::M,
+    Key::N,
+    Key::O,
+    Key::P,
+    Key::Q,
+    Key::R,
+    Key::S,
+    Key::T,
+    Key::U,
+    Key::V,
+    Key::W,
+    Key::X,
+    Key::Y,
+    Key::Z,
+];
+
+/// Menus that can be shown on top of the map. While one is open, it takes all input.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Menu {
+    /// Lists what the player is carrying.
+    Inventory,
+    /// Lists what the player is carrying, and drops the chosen item.
+    Drop,
+    /// Lists what the player is carrying, and uses the chosen item.
+    Use,
+    /// Lists what the player is carrying, and equips the chosen item.
+    Equip,
+    /// Shows the player's stats and equipment. Choosing a slot takes off what's in it.
+    Character,
+    /// Shows what each monster knows about its surroundings. Meant for debugging.
+    Perception,
+}
+
+/// The target-selection cursor, shown while the player is choosing where to use an ability.
+struct TargetCursor {
+    /// Index of the ability in the player's Abilities.
+    ability: usize,
+    point: WorldPoint,
+}
 
 struct Iterativ {
     tiles: Tiles,
@@ -31,6 +98,19 @@ struct Iterativ {
     window: Window,
     graphics: Graphics,
     log_renderer: EventLogRenderer,
+    overlay_renderer: OverlayRenderer,
+    /// Draws the status line at the bottom of the screen.
+    hud_renderer: FontRenderer,
+    menu: Option<Menu>,
+    /// Projectile paths that are currently being drawn, along with how many more frames to draw
+    /// them for.
+    projectiles: Vec<(Vec<WorldPoint>, u32)>,
+    target_cursor: Option<TargetCursor>,
+    /// Tiles hit by area effects during the last update. These are highlighted for one frame.
+    highlights: Vec<WorldPoint>,
+    /// Set while the player is resting. Holds the player's hp as of the last turn, so that resting
+    /// can be interrupted if they get hurt.
+    resting: Option<i32>,
 }
 
 impl Iterativ {
@@ -41,7 +121,17 @@ impl Iterativ {
             (TILE_SIZE as f32, TILE_SIZE as f32).into(),
         )
         .await?;
-        let mut state = Engine::new();
+        let mut state = Engine::new(seed());
+        let player_base = BaseStats {
+            max_hp: 20,
+            attack: 3,
+            accuracy: 80,
+            evasion: 10,
+            crit_chance: 10,
+            damage_type: DamageType::Physical,
+            defense: 0,
+            stealth: 0,
+        };
         let player = state
             .world
             .create_entity()
@@ -51,39 +141,71 @@ impl Iterativ {
             .with(Position((5, 5).into()))
             .with(AIComponent(Box::new(PlayerAI)))
             .with(IsPlayer)
+            .with(Faction::Player)
             .with(Visible {
                 tile_id: TileId::Player,
             })
-            .with(CombatStats {
-                max_hp: 20,
-                hp: 20,
-                attack: 3,
-            })
+            .with(player_base)
+            .with(player_base.derive(player_base.max_hp, &[]))
+            .with(Equipment::default())
+            .with(Experience::default())
+            .with(Perks::default())
             .with(Initiative::new(10))
+            .with(RangedWeapon { range: 8, ammo: 10 })
+            .with(Abilities::new(&[
+                abilities::FIREBOLT,
+                abilities::FROST_BURST,
+                abilities::FIREBALL,
+                abilities::FLAME_BREATH,
+                abilities::SHIELD_BASH,
+                abilities::REND,
+                abilities::QUICKEN,
+                abilities::SECOND_WIND,
+            ]))
+            .with(Energy::new(10))
+            .with(Inventory::new(10))
+            .with(Regeneration::new(10))
             .with(BlocksMovement)
             .build();
-        state
-            .world
-            .create_entity()
-            .with(Name {
-                name: "the swarmer".to_string(),
-            })
-            .with(Position((0, 0).into()))
-            .with(Visible {
-                tile_id: TileId::Grunt,
-            })
-            .with(CombatStats {
-                max_hp: 5,
-                hp: 5,
-                attack: 1,
-            })
-            .with(Initiative::new(20))
-            .with(BlocksMovement)
-            .with(AIComponent(Box::new(Swarm { target: player })))
-            .build();
+        let blueprints = blueprints::Blueprints::load();
+        blueprints.spawn_monster(&mut state.world, "swarmer", (0, 0).into());
+        blueprints.spawn_patrol(
+            &mut state.world,
+            "swarmer",
+            &[
+                (30, 3).into(),
+                (30, 20).into(),
+                (50, 20).into(),
+                (50, 3).into(),
+            ],
+        );
+        blueprints.spawn_pack(
+            &mut state.world,
+            "swarmer",
+            &[(40, 14).into(), (41, 14).into(), (40, 15).into()],
+        );
+        blueprints.spawn_monster(&mut state.world, "hound", (6, 4).into());
+        blueprints.spawn_monster(&mut state.world, "goblin_archer", (20, 12).into());
+        for (id, position) in &[
+            ("healing_potion", (7, 5)),
+            ("teleport_scroll", (4, 9)),
+            ("haste_scroll", (9, 9)),
+            ("rusty_sword", (8, 6)),
+            ("leather_armor", (6, 8)),
+            ("frost_ring", (3, 7)),
+            ("vitality_amulet", (12, 3)),
+            ("regeneration_ring", (14, 8)),
+        ] {
+            blueprints.spawn_item(&mut state.world, id, (*position).into());
+        }
+        state.world.insert(blueprints);
         state.world.insert(PlayerId(player));
         state.world.insert(event_log::EventLog::new());
-        state.world.insert(map::Map::new(WIDTH, HEIGHT));
+        let mut map = map::Map::new(WIDTH, MAP_HEIGHT);
+        for y in 2..8 {
+            map.set_tile((10, y).into(), TileType::Wall);
+        }
+        state.world.insert(map);
 
         let font = VectorFont::from_bytes(include_bytes!("../static/white_rabbit.ttf").to_vec());
         let renderer = font.to_renderer(&graphics, 16.0)?;
@@ -100,38 +222,145 @@ impl Iterativ {
             ),
             renderer,
         );
+        let overlay_renderer = OverlayRenderer::new(
+            Rectangle::new(
+                Vector::new((WIDTH * TILE_SIZE) as f32 / 4.0, TILE_SIZE as f32 * 2.0),
+                Vector::new(
+                    (WIDTH * TILE_SIZE) as f32 / 2.0,
+                    ((MAP_HEIGHT - 4) * TILE_SIZE) as f32,
+                ),
+            ),
+            font.to_renderer(&graphics, 16.0)?,
+        );
+        let hud_renderer = font.to_renderer(&graphics, 16.0)?;
         Ok(Iterativ {
             window,
             graphics,
             tiles,
             state,
             log_renderer,
+            overlay_renderer,
+            hud_renderer,
+            menu: None,
+            projectiles: vec![],
+            target_cursor: None,
+            resting: None,
+            highlights: vec![],
         })
     }
 
     fn draw(&mut self) -> Result<()> {
         self.graphics.clear(Color::BLACK);
 
+        let map = self.state.world.fetch::<map::Map>();
+        for y in 0..map.height() {
+            for x in 0..map.width() {
+                let point = WorldPoint::new(x, y);
+                if map.tile(point) == TileType::Wall {
+                    self.graphics
+                        .fill_rect(&tile_rect(point), Color::from_rgba(96, 96, 96, 1.0));
+                }
+            }
+        }
+
         let positions = self.state.world.read_storage::<Position>();
         let visibles = self.state.world.read_storage::<Visible>();
 
         let graphics = &mut self.graphics;
 
-        for (pos, vis) in (&positions, &visibles).join() {
+        let blocks = self.state.world.read_storage::<BlocksMovement>();
+        // Draw things that don't block movement (like corpses) first, so that anything standing on
+        // top of them is drawn over them.
+        let mut drawables: Vec<_> = (&positions, &visibles, blocks.maybe()).join().collect();
+        drawables.sort_by_key(|(_, _, blocks)| blocks.is_some());
+        for (pos, vis, _) in drawables {
             // XXX: get rid of these magic constants.
             let vec = Vector {
                 x: 16.0 * (pos.0.x as f32),
                 y: 16.0 * (pos.0.y as f32),
             };
             graphics.draw_image(
-                &self.tiles.tile(vis.tile_id),
+                self.tiles.tile(vis.tile_id),
                 Rectangle::new(vec, self.tiles.size()),
             );
         }
 
+        for (path, _) in &self.projectiles {
+            for point in path {
+                let rect = tile_rect(*point);
+                let quarter = rect.size() / 4.0;
+                graphics.fill_rect(
+                    &Rectangle::new(rect.pos + quarter, quarter * 2.0),
+                    Color::YELLOW,
+                );
+            }
+        }
+        for (_, frames) in &mut self.projectiles {
+            *frames -= 1;
+        }
+        self.projectiles.retain(|(_, frames)| *frames > 0);
+
+        for point in &self.highlights {
+            graphics.fill_rect(&tile_rect(*point), Color::ORANGE.with_alpha(0.5));
+        }
+
+        if let Some(cursor) = &self.target_cursor {
+            let ability = self.state.player_ability(cursor.ability);
+            let player_pos = self.state.player_position();
+            if let (Some(ability), Some(player_pos)) = (ability, player_pos) {
+                let shows_area = matches!(
+                    ability.targeting,
+                    Targeting::Area { .. } | Targeting::Cone { .. }
+                );
+                if shows_area && ability.can_target(&map, player_pos, cursor.point) {
+                    for point in ability.affected_tiles(&map, player_pos, cursor.point) {
+                        graphics.fill_rect(&tile_rect(point), Color::CYAN.with_alpha(0.3));
+                    }
+                }
+            }
+            graphics.stroke_rect(&tile_rect(cursor.point), Color::CYAN);
+        }
+
         let event_log = self.state.world.fetch::<event_log::EventLog>();
         self.log_renderer.render(&event_log, &mut self.graphics)?;
 
+        let mut hud = vec![];
+        if let Some(stats) = self.state.player_stats() {
+            hud.push(format!("HP: {}/{}", stats.hp, stats.max_hp));
+        }
+        if let Some(experience) = self.state.player_experience() {
+            hud.push(format!(
+                "Level {} ({}/{} XP)",
+                experience.level,
+                experience.xp,
+                experience.xp_needed()
+            ));
+        }
+        self.hud_renderer.draw(
+            &mut self.graphics,
+            &hud.join("   "),
+            Color::WHITE,
+            Vector::new(0.0, ((HEIGHT - 1) * TILE_SIZE) as f32),
+        )?;
+
+        if self.state.loop_state() == LoopState::ChoosingPerk {
+            let lines: Vec<String> = self
+                .state
+                .perk_offer()
+                .into_iter()
+                .zip('a'..='z')
+                .map(|((name, description), letter)| {
+                    format!("{}) {}: {}", letter, name, description)
+                })
+                .collect();
+            self.overlay_renderer
+                .render(&mut self.graphics, "Choose a perk:", &lines)?;
+        } else if let Some(menu) = self.menu {
+            let (title, lines) = self.menu_contents(menu);
+            self.overlay_renderer
+                .render(&mut self.graphics, &title, &lines)?;
+        }
+
         self.graphics.present(&self.window)?;
         Ok(())
     }
@@ -145,49 +374,294 @@ impl Iterativ {
             if !ev.is_down() {
                 return Ok(());
             }
-            match ev.key() {
-                Key::H => self.state.set_action(Action::Move {
-                    motion: (-1, 0).into(),
-                }),
-                Key::J => self.state.set_action(Action::Move {
-                    motion: (0, 1).into(),
-                }),
-                Key::K => self.state.set_action(Action::Move {
-                    motion: (0, -1).into(),
-                }),
-                Key::L => self.state.set_action(Action::Move {
-                    motion: (1, 0).into(),
-                }),
-                Key::Y => self.state.set_action(Action::Move {
-                    motion: (-1, -1).into(),
-                }),
-                Key::U => self.state.set_action(Action::Move {
-                    motion: (1, -1).into(),
-                }),
-                Key::B => self.state.set_action(Action::Move {
-                    motion: (-1, 1).into(),
-                }),
-                Key::N => self.state.set_action(Action::Move {
-                    motion: (1, 1).into(),
-                }),
+            let key = ev.key();
+            // Any key interrupts resting.
+            self.resting = None;
+            if self.state.loop_state() == LoopState::ChoosingPerk {
+                // The perk has to be picked before anything else can be done.
+                if let Some(index) = LETTER_KEYS.iter().position(|letter| *letter == key) {
+                    if self.state.choose_perk(index) {
+                        self.menu = None;
+                        self.target_cursor = None;
+                    }
+                }
+                return Ok(());
+            }
+            if self.menu.is_some() {
+                self.menu_event(key);
+                return Ok(());
+            }
+            if self.target_cursor.is_some() {
+                self.target_event(key);
+                return Ok(());
+            }
+            if let Some(motion) = key_motion(key) {
+                self.state.set_action(Action::Move { motion });
+                return Ok(());
+            }
+            match key {
+                Key::F => {
+                    if let Some(target) = self.state.nearest_target() {
+                        self.state.set_action(Action::RangedAttack { target })
+                    }
+                }
+                Key::G => self.state.set_action(Action::PickUp),
+                Key::Period => self.state.set_action(Action::Wait),
+                Key::R => {
+                    self.resting = self.state.player_stats().map(|stats| stats.hp);
+                }
+                Key::I => self.menu = Some(Menu::Inventory),
+                Key::D => self.menu = Some(Menu::Drop),
+                Key::Q => self.menu = Some(Menu::Use),
+                Key::W => self.menu = Some(Menu::Equip),
+                Key::C => self.menu = Some(Menu::Character),
+                Key::P => self.menu = Some(Menu::Perception),
+                Key::Key1 => self.begin_ability(0),
+                Key::Key2 => self.begin_ability(1),
+                Key::Key3 => self.begin_ability(2),
+                Key::Key4 => self.begin_ability(3),
+                Key::Key5 => self.begin_ability(4),
+                Key::Key6 => self.begin_ability(5),
+                Key::Key7 => self.begin_ability(6),
+                Key::Key8 => self.begin_ability(7),
+                Key::Key9 => self.begin_ability(8),
                 _ => (),
             }
         }
         Ok(())
     }
 
+    /// The title and lines of text to show for the given menu.
+    fn menu_contents(&self, menu: Menu) -> (String, Vec<String>) {
+        let title = match menu {
+            Menu::Inventory => "You are carrying:",
+            Menu::Drop => "Drop which item?",
+            Menu::Use => "Use which item?",
+            Menu::Equip => "Equip which item?",
+            Menu::Character => "Your character",
+            Menu::Perception => "Monster perception",
+        };
+        let mut lines: Vec<String> = vec![];
+        if menu == Menu::Perception {
+            return (title.to_string(), self.state.perception_report());
+        }
+        if menu == Menu::Character {
+            if let Some(experience) = self.state.player_experience() {
+                lines.push(format!(
+                    "Level: {} ({}/{} XP)",
+                    experience.level,
+                    experience.xp,
+                    experience.xp_needed()
+                ));
+            }
+            if let Some(stats) = self.state.player_stats() {
+                lines.push(format!("HP: {}/{}", stats.hp, stats.max_hp));
+                lines.push(format!(
+                    "Attack: {} ({} damage)",
+                    stats.attack,
+                    stats.damage_type.name()
+                ));
+                lines.push(format!("Accuracy: {}", stats.accuracy));
+                lines.push(format!("Evasion: {}", stats.evasion));
+                lines.push(format!("Critical chance: {}%", stats.crit_chance));
+                lines.push(format!("Defense: {}", stats.defense));
+                lines.push(format!("Stealth: {}", stats.stealth));
+                lines.push(String::new());
+            }
+            let perks = self.state.player_perks();
+            if !perks.is_empty() {
+                lines.push(format!("Perks: {}", perks.join(", ")));
+                lines.push(String::new());
+            }
+            let equipment = self.state.player_equipment();
+            for ((slot, item), letter) in equipment.into_iter().zip('a'..='z') {
+                let item = item.unwrap_or_else(|| "nothing".to_string());
+                lines.push(format!("{}) {}: {}", letter, slot.name(), item));
+            }
+            return (title.to_string(), lines);
+        }
+        lines.extend(
+            self.state
+                .player_inventory()
+                .into_iter()
+                .zip('a'..='z')
+                .map(|((_, name), letter)| format!("{}) {}", letter, name)),
+        );
+        if lines.is_empty() {
+            lines.push("nothing".to_string());
+        }
+        (title.to_string(), lines)
+    }
+
+    /// Handles input while a menu is open: letters pick an entry, and escape closes the menu.
+    fn menu_event(&mut self, key: Key) {
+        let menu = match self.menu {
+            Some(menu) => menu,
+            None => return,
+        };
+        if key == Key::Escape {
+            self.menu = None;
+            return;
+        }
+        let index = match LETTER_KEYS.iter().position(|letter| *letter == key) {
+            Some(index) => index,
+            None => return,
+        };
+        match menu {
+            Menu::Inventory | Menu::Perception => (),
+            Menu::Drop => {
+                if let Some((item, _)) = self.state.player_inventory().get(index) {
+                    self.state.set_action(Action::Drop { item: *item });
+                    self.menu = None;
+                }
+            }
+            Menu::Use => {
+                if let Some((item, _)) = self.state.player_inventory().get(index) {
+                    self.state.set_action(Action::UseItem { item: *item });
+                    self.menu = None;
+                }
+            }
+            Menu::Equip => {
+                if let Some((item, _)) = self.state.player_inventory().get(index) {
+                    self.state.set_action(Action::Equip { item: *item });
+                    self.menu = None;
+                }
+            }
+            Menu::Character => {
+                if let Some(slot) = EquipSlot::ALL.get(index) {
+                    self.state.set_action(Action::Unequip { slot: *slot });
+                    self.menu = None;
+                }
+            }
+        }
+    }
+
+    /// Starts using the ability in the given slot. Abilities that only affect the player are used
+    /// immediately; everything else brings up the target-selection cursor.
+    fn begin_ability(&mut self, index: usize) {
+        let (ability, player_pos) = match (
+            self.state.player_ability(index),
+            self.state.player_position(),
+        ) {
+            (Some(ability), Some(player_pos)) => (ability, player_pos),
+            _ => return,
+        };
+        if ability.targeting == Targeting::OnSelf {
+            self.state.set_action(Action::UseAbility {
+                ability: index,
+                target: player_pos,
+            });
+        } else {
+            self.target_cursor = Some(TargetCursor {
+                ability: index,
+                point: self.state.nearest_target().unwrap_or(player_pos),
+            });
+        }
+    }
+
+    /// Handles input while the target-selection cursor is up: the movement keys move the cursor,
+    /// enter confirms the target, and escape cancels.
+    fn target_event(&mut self, key: Key) {
+        let cursor = match &mut self.target_cursor {
+            Some(cursor) => cursor,
+            None => return,
+        };
+        if let Some(motion) = key_motion(key) {
+            let moved = cursor.point + motion;
+            if moved.x >= 0 && moved.y >= 0 && moved.x < WIDTH && moved.y < MAP_HEIGHT {
+                cursor.point = moved;
+            }
+            return;
+        }
+        match key {
+            Key::Return | Key::F => {
+                self.state.set_action(Action::UseAbility {
+                    ability: cursor.ability,
+                    target: cursor.point,
+                });
+                self.target_cursor = None;
+            }
+            Key::Escape => self.target_cursor = None,
+            _ => (),
+        }
+    }
+
+    /// Keeps the player waiting until they've regenerated to full health, or until something hurts
+    /// them.
+    fn rest(&mut self) {
+        let last_hp = match self.resting {
+            Some(hp) => hp,
+            None => return,
+        };
+        match self.state.player_stats() {
+            Some(stats) if stats.hp >= last_hp && stats.hp < stats.max_hp => {
+                self.state.set_action(Action::Wait);
+                self.resting = Some(stats.hp);
+            }
+            _ => self.resting = None,
+        }
+    }
+
     fn update(&mut self) -> Result<()> {
+        if self.state.loop_state() == LoopState::WaitingForPlayer {
+            self.rest();
+        }
         match self.state.loop_state() {
             LoopState::WaitingForPlayer | LoopState::Looping => {
                 self.state.tick();
                 self.state.world.maintain();
+                let fired = std::mem::take(&mut self.state.world.fetch_mut::<Projectiles>().0);
+                self.projectiles
+                    .extend(fired.into_iter().map(|path| (path, PROJECTILE_FRAMES)));
+                self.highlights =
+                    std::mem::take(&mut self.state.world.fetch_mut::<HighlightedTiles>().0);
             }
-            LoopState::GameOver => {}
+            LoopState::ChoosingPerk | LoopState::GameOver => {}
         }
         Ok(())
     }
 }
 
+/// The direction that a movement key moves in, if it is one.
+fn key_motion(key: Key) -> Option<WorldVector> {
+    let motion = match key {
+        Key::H => (-1, 0),
+        Key::J => (0, 1),
+        Key::K => (0, -1),
+        Key::L => (1, 0),
+        Key::Y => (-1, -1),
+        Key::U => (1, -1),
+        Key::B => (-1, 1),
+        Key::N => (1, 1),
+        _ => return None,
+    };
+    Some(motion.into())
+}
+
+/// The screen area covered by the tile at the given point.
+fn tile_rect(point: WorldPoint) -> Rectangle {
+    Rectangle::new(
+        Vector::new((point.x * TILE_SIZE) as f32, (point.y * TILE_SIZE) as f32),
+        Vector::new(TILE_SIZE as f32, TILE_SIZE as f32),
+    )
+}
+
+/// Picks a seed for a new run.
+#[cfg(target_arch = "wasm32")]
+fn seed() -> u64 {
+    stdweb::web::Date::now() as u64
+}
+
+/// Picks a seed for a new run.
+#[cfg(not(target_arch = "wasm32"))]
+fn seed() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system clock is before the epoch")
+        .as_secs()
+}
+
 #[cfg(target_arch = "wasm32")]
 fn wasm_panic_hook(info: &std::panic::PanicInfo) {
     use stdweb::console;
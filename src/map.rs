@@ -1,4 +1,5 @@
-use specs::Entity;
+use specs::{world::Index, Entity};
+use std::collections::{HashMap, VecDeque};
 
 use crate::geometry::*;
 
@@ -25,10 +26,16 @@ pub struct Map {
     // position 1 is to the right of that, etc).
     /// The tile that's located at each position of the map.
     tiles: Vec<TileType>,
-    /// All entities located at a given position.
-    pub entities: Vec<Vec<Entity>>,
-    /// For each tile that has a blocking entity on it, this returns that entity.
-    pub blockers: Vec<Option<Entity>>,
+    /// All entities located at a given position, in order of their ids.
+    entities: Vec<Vec<Entity>>,
+    /// For each tile that has a blocking entity on it, this returns that entity. If there's more
+    /// than one, it's the one with the highest id.
+    blockers: Vec<Option<Entity>>,
+    /// Where each entity on the map is, and whether it blocks movement, keyed by entity id.
+    placements: HashMap<Index, (WorldPoint, bool)>,
+    /// Whether the entities have been added since the map was created. MapUpdateSystem fills in a
+    /// new map from scratch, and after that only updates the entities that changed.
+    indexed: bool,
 }
 
 impl Map {
@@ -42,19 +49,54 @@ impl Map {
             tiles: vec![TileType::Floor; vec_size],
             blockers: vec![None; vec_size],
             entities: vec![vec![]; vec_size],
+            placements: HashMap::new(),
+            indexed: false,
         }
     }
 
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
     pub fn size(&self) -> usize {
         (self.width * self.height) as usize
     }
 
+    /// Whether the point lies inside the map.
+    pub fn contains(&self, point: WorldPoint) -> bool {
+        point.x >= 0 && point.y >= 0 && point.x < self.width && point.y < self.height
+    }
+
     fn idx(&self, point: WorldPoint) -> usize {
         assert!(point.x >= 0);
         assert!(point.y >= 0);
         (self.width * point.y + point.x) as usize
     }
 
+    pub fn tile(&self, point: WorldPoint) -> TileType {
+        self.tiles[self.idx(point)]
+    }
+
+    pub fn set_tile(&mut self, point: WorldPoint, tile: TileType) {
+        let idx = self.idx(point);
+        self.tiles[idx] = tile;
+    }
+
+    /// Whether the entities have been added since the map was created.
+    pub fn is_indexed(&self) -> bool {
+        self.indexed
+    }
+
+    /// Records that the entities have been added, so that from now on only changes need to be
+    /// applied.
+    pub fn mark_indexed(&mut self) {
+        self.indexed = true;
+    }
+
     /// Clears out all entities, including the cached tile blocking information. Does not modify
     /// the tiles themselves.
     pub fn clear_entities(&mut self) {
@@ -62,14 +104,45 @@ impl Map {
         for entities in &mut self.entities {
             entities.clear();
         }
+        self.placements.clear();
     }
 
+    /// Puts an entity at the given point, taking it away from wherever it was before.
     pub fn add_entity(&mut self, point: WorldPoint, entity: Entity, blocks: bool) {
+        self.remove_entity(entity.id());
         let idx = self.idx(point);
-        if blocks {
-            self.blockers[idx] = Some(entity)
+        let entities = &mut self.entities[idx];
+        let slot = entities.partition_point(|other| other.id() < entity.id());
+        entities.insert(slot, entity);
+        self.placements.insert(entity.id(), (point, blocks));
+        self.update_blocker(idx);
+    }
+
+    /// Takes the entity with the given id off the map. Does nothing if it isn't on it.
+    pub fn remove_entity(&mut self, id: Index) {
+        if let Some((point, _)) = self.placements.remove(&id) {
+            let idx = self.idx(point);
+            self.entities[idx].retain(|other| other.id() != id);
+            self.update_blocker(idx);
         }
-        self.entities[idx].push(entity);
+    }
+
+    fn update_blocker(&mut self, idx: usize) {
+        let placements = &self.placements;
+        self.blockers[idx] = self.entities[idx]
+            .iter()
+            .rev()
+            .find(|entity| {
+                placements
+                    .get(&entity.id())
+                    .is_some_and(|(_, blocks)| *blocks)
+            })
+            .copied();
+    }
+
+    /// All entities located at the given point.
+    pub fn entities_at(&self, point: WorldPoint) -> &[Entity] {
+        &self.entities[self.idx(point)]
     }
 
     /// Is movement onto this tile blocked?
@@ -83,4 +156,115 @@ impl Map {
     pub fn blockers(&self, point: WorldPoint) -> Option<Entity> {
         self.blockers[self.idx(point)]
     }
+
+    /// Traces the path of a projectile fired from `from` towards `to`. The path stops at the
+    /// first solid tile or blocking entity (which is included, since that's what the projectile
+    /// hits), at the edge of the map, or after `range` tiles.
+    pub fn line_of_fire(&self, from: WorldPoint, to: WorldPoint, range: i32) -> Vec<WorldPoint> {
+        let mut path = vec![];
+        for point in line(from, to).into_iter().take(range.max(0) as usize) {
+            if !self.contains(point) {
+                break;
+            }
+            path.push(point);
+            if self.is_blocked(point) {
+                break;
+            }
+        }
+        path
+    }
+
+    /// Whether there's an unobstructed view from `from` to `to`. Only solid tiles block sight;
+    /// entities don't.
+    pub fn has_line_of_sight(&self, from: WorldPoint, to: WorldPoint) -> bool {
+        let points = line(from, to);
+        points
+            .iter()
+            .take(points.len().saturating_sub(1))
+            .all(|point| self.contains(*point) && !self.tile(*point).is_solid())
+    }
+
+    /// The tiles that can be seen from `center`, out to `radius` tiles away. Walls block sight.
+    pub fn field_of_view(&self, center: WorldPoint, radius: i32) -> Vec<WorldPoint> {
+        points_within(center, radius)
+            .into_iter()
+            .filter(|point| self.contains(*point) && self.has_line_of_sight(center, *point))
+            .collect()
+    }
+
+    /// The tiles caught in an explosion at `center`: everything within `radius` that the blast
+    /// can reach without going through a wall.
+    pub fn blast_area(&self, center: WorldPoint, radius: i32) -> Vec<WorldPoint> {
+        self.field_of_view(center, radius)
+    }
+
+    /// The tiles that a noise made at `origin` can be heard on. Unlike sight, sound travels around
+    /// corners: it spreads out one tile at a time, for up to `radius` steps, and only walls stop it.
+    pub fn noise_area(&self, origin: WorldPoint, radius: i32) -> Vec<WorldPoint> {
+        if !self.contains(origin) {
+            return vec![];
+        }
+        let mut reached = vec![false; self.size()];
+        reached[self.idx(origin)] = true;
+        let mut area = vec![];
+        let mut frontier = VecDeque::new();
+        frontier.push_back((origin, 0));
+        while let Some((point, steps)) = frontier.pop_front() {
+            area.push(point);
+            if steps == radius {
+                continue;
+            }
+            for next in points_within(point, 1) {
+                if !self.contains(next) || self.tile(next).is_solid() {
+                    continue;
+                }
+                let idx = self.idx(next);
+                if !reached[idx] {
+                    reached[idx] = true;
+                    frontier.push_back((next, steps + 1));
+                }
+            }
+        }
+        area
+    }
+
+    /// The tiles covered by a 90-degree cone of length `radius`, starting at `origin` and aimed at
+    /// `toward`. Like `blast_area`, walls block the cone.
+    pub fn cone_area(
+        &self,
+        origin: WorldPoint,
+        toward: WorldPoint,
+        radius: i32,
+    ) -> Vec<WorldPoint> {
+        points_within(origin, radius)
+            .into_iter()
+            .filter(|point| {
+                self.contains(*point)
+                    && in_cone(origin, toward, *point, std::f32::consts::FRAC_PI_4)
+                    && self.has_line_of_sight(origin, *point)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noise_goes_around_walls() {
+        let mut map = Map::new(10, 10);
+        // A wall down the middle, with a gap at the bottom.
+        for y in 0..9 {
+            map.set_tile((3, y).into(), TileType::Wall);
+        }
+        let origin = (1, 1).into();
+        let heard = |radius| map.noise_area(origin, radius);
+        assert!(!heard(4).contains(&(5, 1).into()));
+        assert!(!heard(15).contains(&(5, 1).into()));
+        assert!(heard(16).contains(&(5, 1).into()));
+        assert!(heard(20).iter().all(|point| !map.tile(*point).is_solid()));
+        assert_eq!(heard(1).len(), 9);
+        assert!(map.noise_area((-1, 0).into(), 3).is_empty());
+    }
 }
@@ -0,0 +1,38 @@
+use quicksilver::{
+    geom::{Rectangle, Vector},
+    graphics::{Color, FontRenderer, Graphics},
+    Result,
+};
+
+/// Draws a box with a title and a list of lines on top of the map. Used for screens like the
+/// inventory.
+pub struct OverlayRenderer {
+    bounds: Rectangle,
+    renderer: FontRenderer,
+}
+
+impl OverlayRenderer {
+    pub fn new(bounds: Rectangle, renderer: FontRenderer) -> Self {
+        Self { bounds, renderer }
+    }
+
+    pub fn render(&mut self, graphics: &mut Graphics, title: &str, lines: &[String]) -> Result<()> {
+        graphics.fill_rect(&self.bounds, Color::BLACK.with_alpha(0.85));
+        graphics.stroke_rect(&self.bounds, Color::WHITE);
+        let mut text = title.to_string();
+        text.push('\n');
+        for line in lines {
+            text.push('\n');
+            text.push_str(line);
+        }
+        let margin = Vector::new(8.0, 8.0);
+        self.renderer.draw_wrapping(
+            graphics,
+            &text,
+            Some(self.bounds.size().x - 2.0 * margin.x),
+            Color::WHITE,
+            self.bounds.pos + margin,
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,78 @@
+//! Packs are groups of monsters that hunt together. Members share what they've seen, so when one
+//! of them spots an enemy the whole pack comes running, and they spread out around their prey
+//! instead of queueing up behind each other.
+use specs::{prelude::*, Component};
+use std::collections::HashMap;
+
+use crate::components::{CombatStats, Position};
+use crate::geometry::{points_within, WorldPoint};
+use crate::map::Map;
+
+/// Marks an entity as belonging to the pack with this index in `Packs`.
+#[derive(Component, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PackMember(pub usize);
+
+/// An enemy that a pack member can currently see.
+#[derive(Copy, Clone, Debug)]
+pub struct Sighting {
+    pub target: Entity,
+    pub seen_at: WorldPoint,
+    /// The member that saw it. The sighting is dropped once this member loses sight of the target.
+    pub spotter: Entity,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Pack {
+    pub sighting: Option<Sighting>,
+    /// The tile next to the target that each member is heading for, so that no two members go for
+    /// the same one.
+    pub claims: HashMap<Entity, WorldPoint>,
+}
+
+/// Every pack in the game. Stored as a resource.
+#[derive(Debug, Default)]
+pub struct Packs(pub Vec<Pack>);
+
+impl Packs {
+    /// Starts a new, empty pack, and returns the component that makes an entity part of it.
+    pub fn create(&mut self) -> PackMember {
+        self.0.push(Pack::default());
+        PackMember(self.0.len() - 1)
+    }
+
+    pub fn get_mut(&mut self, member: PackMember) -> Option<&mut Pack> {
+        self.0.get_mut(member.0)
+    }
+}
+
+/// Picks the tile next to `target_pos` that `me` should attack from: the closest free one that no
+/// other member of its pack is already heading for. Returns `None` if `me` isn't in a pack, or if
+/// every tile around the target is taken.
+pub fn surround(world: &World, me: Entity, target_pos: WorldPoint) -> Option<WorldPoint> {
+    let member = *world.read_component::<PackMember>().get(me)?;
+    let my_pos = world.read_component::<Position>().get(me)?.0;
+    let map = world.fetch::<Map>();
+    let entities = world.entities();
+    let stats = world.read_component::<CombatStats>();
+    let mut packs = world.fetch_mut::<Packs>();
+    let pack = packs.get_mut(member)?;
+    pack.claims
+        .retain(|claimer, _| entities.is_alive(*claimer) && stats.contains(*claimer));
+    let taken: Vec<WorldPoint> = pack
+        .claims
+        .iter()
+        .filter(|(claimer, _)| **claimer != me)
+        .map(|(_, slot)| *slot)
+        .collect();
+    let slot = points_within(target_pos, 1)
+        .into_iter()
+        .filter(|point| *point != target_pos && map.contains(*point))
+        .filter(|point| *point == my_pos || !map.is_blocked(*point))
+        .filter(|point| !taken.contains(point))
+        .min_by_key(|point| (*point - my_pos).square_length());
+    match slot {
+        Some(slot) => pack.claims.insert(me, slot),
+        None => pack.claims.remove(&me),
+    };
+    slot
+}
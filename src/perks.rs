@@ -0,0 +1,140 @@
+//! Perks are permanent upgrades that the player picks from whenever they level up. The pool of
+//! perks is defined in `static/data/perks.toml`.
+use rand::seq::SliceRandom;
+use serde::Deserialize;
+use specs::prelude::*;
+use std::collections::HashMap;
+
+use crate::abilities::{self, Abilities, KnownAbility};
+use crate::components::*;
+use crate::engine::GameRng;
+
+/// How many perks are offered at each level-up.
+pub const PERKS_PER_LEVEL: usize = 3;
+
+/// What a perk does once it's been picked.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PerkEffect {
+    /// Permanently adds to the entity's base stats.
+    Stats { bonus: StatBonus },
+    /// Makes the entity's turns come around this many ticks sooner.
+    Initiative { amount: i32 },
+    /// Makes the entity regenerate 1 hp this many turns sooner.
+    Regeneration { amount: i32 },
+    /// Heals the entity for this percentage of all the damage it deals.
+    Lifesteal { percent: i32 },
+    /// Teaches the entity the ability with the given name.
+    Ability { name: String },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Perk {
+    pub name: String,
+    pub description: String,
+    /// Unique perks are only offered until they've been picked once.
+    #[serde(default)]
+    pub unique: bool,
+    pub effect: PerkEffect,
+}
+
+impl Perk {
+    /// Applies the perk's effect to `entity`.
+    pub fn apply(&self, world: &World, entity: Entity) {
+        match &self.effect {
+            PerkEffect::Stats { bonus } => {
+                if let Some(base) = world.write_storage::<BaseStats>().get_mut(entity) {
+                    base.max_hp += bonus.max_hp;
+                    base.attack += bonus.attack;
+                    base.accuracy += bonus.accuracy;
+                    base.evasion += bonus.evasion;
+                    base.crit_chance += bonus.crit_chance;
+                    base.defense += bonus.defense;
+                    base.stealth += bonus.stealth;
+                    if let Some(damage_type) = bonus.damage_type {
+                        base.damage_type = damage_type;
+                    }
+                }
+                // Grant the extra hp straight away rather than leaving the entity wounded.
+                if let Some(stats) = world.write_storage::<CombatStats>().get_mut(entity) {
+                    stats.max_hp += bonus.max_hp;
+                    stats.hp += bonus.max_hp;
+                }
+            }
+            PerkEffect::Initiative { amount } => {
+                if let Some(initiative) = world.write_storage::<Initiative>().get_mut(entity) {
+                    initiative.initial = (initiative.initial - amount).max(1);
+                }
+            }
+            PerkEffect::Regeneration { amount } => {
+                if let Some(regeneration) = world.write_storage::<Regeneration>().get_mut(entity) {
+                    regeneration.base_interval = (regeneration.base_interval - amount).max(1);
+                }
+            }
+            PerkEffect::Lifesteal { percent } => {
+                let mut lifesteal = world.write_storage::<Lifesteal>();
+                let current = lifesteal.get(entity).map_or(0, |lifesteal| lifesteal.0);
+                lifesteal
+                    .insert(entity, Lifesteal(current + percent))
+                    .expect("couldn't add lifesteal");
+            }
+            PerkEffect::Ability { name } => {
+                // PerkPool::load has already checked that the ability exists.
+                let ability = abilities::named(name);
+                let mut known = world.write_storage::<Abilities>();
+                if let (Some(ability), Some(abilities)) = (ability, known.get_mut(entity)) {
+                    abilities.0.push(KnownAbility {
+                        ability,
+                        cooldown: 0,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Every perk in the game, keyed by id. Stored as a resource.
+#[derive(Debug)]
+pub struct PerkPool(pub HashMap<String, Perk>);
+
+impl PerkPool {
+    /// Loads the perks that are bundled with the game. Panics if the data file is malformed, or if
+    /// a perk teaches an ability that doesn't exist.
+    pub fn load() -> Self {
+        let pool = PerkPool(
+            toml::from_str(include_str!("../static/data/perks.toml"))
+                .expect("couldn't parse perks"),
+        );
+        for (id, perk) in &pool.0 {
+            if let PerkEffect::Ability { name } = &perk.effect {
+                if abilities::named(name).is_none() {
+                    panic!("perk {} teaches unknown ability {}", id, name);
+                }
+            }
+        }
+        pool
+    }
+
+    /// Picks the ids of up to `PERKS_PER_LEVEL` different perks to offer to an entity with the
+    /// given perks. Unique perks aren't offered if they've been taken already, or if they're in
+    /// an offer that hasn't been picked from yet, since they could be picked from both.
+    pub fn offer(&self, perks: &Perks, rng: &mut GameRng) -> Vec<String> {
+        let unavailable = |id: &String| {
+            perks.taken.contains(id) || perks.offers.iter().any(|offer| offer.contains(id))
+        };
+        let mut available: Vec<&String> = self
+            .0
+            .iter()
+            .filter(|(id, perk)| !perk.unique || !unavailable(id))
+            .map(|(id, _)| id)
+            .collect();
+        // Sort first, since the map's iteration order would otherwise make runs unrepeatable.
+        available.sort();
+        available.shuffle(&mut rng.0);
+        available
+            .into_iter()
+            .take(PERKS_PER_LEVEL)
+            .cloned()
+            .collect()
+    }
+}
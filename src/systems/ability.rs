@@ -0,0 +1,139 @@
+use log::info;
+use specs::prelude::*;
+
+use crate::abilities::*;
+use crate::components::*;
+use crate::engine::{HighlightedTiles, Projectiles};
+use crate::event_log::{Event, EventLog};
+use crate::map::Map;
+
+/// Resolves ability use: pays the ability's cost, starts its cooldown, and applies its effect to
+/// every creature it affects.
+pub struct AbilitySystem;
+
+impl<'a> System<'a> for AbilitySystem {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, AbilityIntent>,
+        WriteStorage<'a, Abilities>,
+        WriteStorage<'a, Energy>,
+        ReadStorage<'a, Position>,
+        ReadExpect<'a, Map>,
+        WriteExpect<'a, Projectiles>,
+        WriteExpect<'a, HighlightedTiles>,
+        WriteStorage<'a, QueuedDamage>,
+        WriteStorage<'a, StatusEffects>,
+        WriteStorage<'a, CombatStats>,
+        WriteExpect<'a, EventLog>,
+        ReadStorage<'a, Name>,
+        ReadStorage<'a, Decay>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut intents,
+            mut abilities,
+            mut energy,
+            positions,
+            map,
+            mut projectiles,
+            mut highlights,
+            mut queues,
+            mut status_effects,
+            mut stats,
+            mut event_log,
+            names,
+            decay,
+        ): Self::SystemData,
+    ) {
+        for (entity, intent, abilities, energy, position) in
+            (&entities, &intents, &mut abilities, &mut energy, &positions).join()
+        {
+            let known = match abilities.0.get_mut(intent.ability) {
+                Some(known) => known,
+                None => continue,
+            };
+            let ability = known.ability;
+            if known.cooldown > 0
+                || energy.current < ability.cost
+                || !ability.can_target(&map, position.0, intent.target)
+            {
+                info!("{:?} can't use {} right now", entity, ability.name);
+                continue;
+            }
+            energy.current -= ability.cost;
+            known.cooldown = ability.cooldown;
+            event_log.log(
+                Event::AbilityUsed {
+                    who: entity,
+                    name: ability.name,
+                }
+                .format(&names),
+            );
+
+            let tiles = ability.affected_tiles(&map, position.0, intent.target);
+            match ability.targeting {
+                Targeting::Ranged { range } => {
+                    projectiles
+                        .0
+                        .push(map.line_of_fire(position.0, intent.target, range))
+                }
+                Targeting::Area { .. } | Targeting::Cone { .. } => {
+                    highlights.0.extend(tiles.iter().copied())
+                }
+                Targeting::OnSelf | Targeting::Adjacent => (),
+            }
+            // Only living creatures are affected, not items or corpses (including ones that died
+            // earlier this turn and haven't been turned into corpses yet).
+            let affected: Vec<Entity> = tiles
+                .iter()
+                .flat_map(|tile| map.entities_at(*tile).iter().copied())
+                .filter(|target| {
+                    stats.get(*target).is_some_and(|stats| stats.hp > 0) && !decay.contains(*target)
+                })
+                .collect();
+
+            for target in affected {
+                match ability.effect {
+                    AbilityEffect::Damage { amount, kind } => QueuedDamage::add(
+                        &mut queues,
+                        target,
+                        Damage {
+                            amount,
+                            kind,
+                            source: entity,
+                            critical: false,
+                        },
+                    ),
+                    AbilityEffect::Heal { amount } => {
+                        if let Some(stats) = stats.get_mut(target) {
+                            let healed = stats.heal(amount);
+                            event_log.log(
+                                Event::Heal {
+                                    who: target,
+                                    amount: healed,
+                                }
+                                .format(&names),
+                            );
+                        }
+                    }
+                    AbilityEffect::Status { kind, duration } => {
+                        StatusEffects::apply(
+                            &mut status_effects,
+                            target,
+                            StatusEffect {
+                                kind,
+                                remaining: duration,
+                                source: entity,
+                            },
+                        );
+                        event_log.log(Event::StatusApplied { who: target, kind }.format(&names));
+                    }
+                }
+            }
+        }
+        intents.clear();
+    }
+}
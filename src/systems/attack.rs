@@ -1,8 +1,22 @@
+use rand::Rng;
 use specs::prelude::*;
 
 use crate::components::*;
+use crate::engine::{GameRng, Noise, Noises};
 use crate::event_log::{Event, EventLog};
 
+/// Critical hits deal this many times the attacker's normal damage.
+const CRITICAL_MULTIPLIER: i32 = 2;
+/// Attacks on sleeping targets deal this many times the normal damage. This covers ranged attacks
+/// too, since those are resolved here as well, and stacks with critical hits.
+const SNEAK_ATTACK_MULTIPLIER: i32 = 2;
+/// How far away the sound of a fight can be heard.
+const ATTACK_NOISE: i32 = 6;
+/// The chance to hit is clamped to this range (in percent), so that no attack is ever guaranteed
+/// to hit or to miss.
+const MIN_HIT_CHANCE: i32 = 5;
+const MAX_HIT_CHANCE: i32 = 95;
+
 pub struct AttackSystem;
 
 impl<'a> System<'a> for AttackSystem {
@@ -13,22 +27,103 @@ impl<'a> System<'a> for AttackSystem {
         WriteStorage<'a, QueuedDamage>,
         WriteExpect<'a, EventLog>,
         ReadStorage<'a, Name>,
+        WriteExpect<'a, GameRng>,
+        ReadStorage<'a, InflictsStatus>,
+        WriteStorage<'a, StatusEffects>,
+        ReadStorage<'a, Perception>,
+        ReadStorage<'a, Position>,
+        WriteExpect<'a, Noises>,
     );
 
     fn run(
         &mut self,
-        (entities, mut intents, stats, mut queues, mut event_log, names): Self::SystemData,
+        (
+            entities,
+            mut intents,
+            stats,
+            mut queues,
+            mut event_log,
+            names,
+            mut rng,
+            inflicts,
+            mut status_effects,
+            perception,
+            positions,
+            mut noises,
+        ): Self::SystemData,
     ) {
-        for (entity, intent, stats) in (&entities, &intents, &stats).join() {
-            QueuedDamage::add(&mut queues, intent.target, stats.attack);
-            event_log.log(
-                Event::Damage {
-                    from: entity,
-                    to: intent.target,
-                    amount: stats.attack,
-                }
-                .format(&names),
+        let rng = &mut rng.0;
+        for (entity, intent, attacker, inflicts) in
+            (&entities, &intents, &stats, inflicts.maybe()).join()
+        {
+            if let Some(position) = positions.get(entity) {
+                noises.0.push(Noise {
+                    source: entity,
+                    origin: position.0,
+                    radius: ATTACK_NOISE,
+                });
+            }
+            let evasion = stats.get(intent.target).map_or(0, |stats| stats.evasion);
+            let hit_chance = (attacker.accuracy - evasion).clamp(MIN_HIT_CHANCE, MAX_HIT_CHANCE);
+            if rng.gen_range(0, 100) >= hit_chance {
+                event_log.log(
+                    Event::Miss {
+                        from: entity,
+                        to: intent.target,
+                    }
+                    .format(&names),
+                );
+                continue;
+            }
+            let critical = rng.gen_range(0, 100) < attacker.crit_chance;
+            let mut amount = if critical {
+                attacker.attack * CRITICAL_MULTIPLIER
+            } else {
+                attacker.attack
+            };
+            let asleep = perception
+                .get(intent.target)
+                .is_some_and(|perception| perception.awareness == Awareness::Asleep);
+            if asleep {
+                amount *= SNEAK_ATTACK_MULTIPLIER;
+                event_log.log(
+                    Event::SneakAttack {
+                        from: entity,
+                        to: intent.target,
+                    }
+                    .format(&names),
+                );
+            }
+            QueuedDamage::add(
+                &mut queues,
+                intent.target,
+                Damage {
+                    amount,
+                    kind: attacker.damage_type,
+                    source: entity,
+                    critical,
+                },
             );
+            if let Some(inflicts) = inflicts {
+                if rng.gen_range(0, 100) < inflicts.chance {
+                    StatusEffects::apply(
+                        &mut status_effects,
+                        intent.target,
+                        StatusEffect {
+                            kind: inflicts.kind,
+                            remaining: inflicts.duration,
+                            source: entity,
+                        },
+                    );
+                    event_log.log(
+                        Event::StatusApplied {
+                            who: intent.target,
+                            kind: inflicts.kind,
+                        }
+                        .format(&names),
+                    );
+                }
+            }
         }
         intents.clear();
     }
@@ -0,0 +1,112 @@
+use rand::Rng;
+use specs::prelude::*;
+
+use crate::components::*;
+use crate::engine::GameRng;
+use crate::event_log::{Event, EventLog};
+use crate::geometry::WorldPoint;
+use crate::map::Map;
+
+/// How many random spots a teleport tries before giving up.
+const TELEPORT_ATTEMPTS: usize = 100;
+
+/// Resolves using consumable items. Used items are destroyed.
+pub struct ConsumableSystem;
+
+impl<'a> System<'a> for ConsumableSystem {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, UseItemIntent>,
+        WriteStorage<'a, Inventory>,
+        ReadStorage<'a, Consumable>,
+        WriteStorage<'a, CombatStats>,
+        WriteStorage<'a, StatusEffects>,
+        WriteStorage<'a, Position>,
+        WriteExpect<'a, Map>,
+        ReadStorage<'a, BlocksMovement>,
+        WriteExpect<'a, GameRng>,
+        WriteExpect<'a, EventLog>,
+        ReadStorage<'a, Name>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut intents,
+            mut inventories,
+            consumables,
+            mut stats,
+            mut status_effects,
+            mut positions,
+            mut map,
+            blocking,
+            mut rng,
+            mut event_log,
+            names,
+        ): Self::SystemData,
+    ) {
+        for (entity, intent, inventory) in (&entities, &intents, &mut inventories).join() {
+            let index = inventory.items.iter().position(|item| *item == intent.item);
+            let (index, consumable) = match (index, consumables.get(intent.item)) {
+                (Some(index), Some(consumable)) => (index, consumable),
+                _ => continue,
+            };
+            inventory.items.remove(index);
+            event_log.log(
+                Event::UseItem {
+                    who: entity,
+                    item: intent.item,
+                }
+                .format(&names),
+            );
+            match consumable.effect {
+                ItemEffect::Heal { amount } => {
+                    if let Some(stats) = stats.get_mut(entity) {
+                        let healed = stats.heal(amount);
+                        event_log.log(
+                            Event::Heal {
+                                who: entity,
+                                amount: healed,
+                            }
+                            .format(&names),
+                        );
+                    }
+                }
+                ItemEffect::Teleport => {
+                    let destination = (0..TELEPORT_ATTEMPTS)
+                        .map(|_| {
+                            WorldPoint::new(
+                                rng.0.gen_range(0, map.width()),
+                                rng.0.gen_range(0, map.height()),
+                            )
+                        })
+                        .find(|point| !map.is_blocked(*point));
+                    if let (Some(destination), Some(position)) =
+                        (destination, positions.get_mut(entity))
+                    {
+                        // The map has already been updated this turn, so move the entity on it
+                        // right away; otherwise something else could step onto the destination.
+                        map.add_entity(destination, entity, blocking.contains(entity));
+                        position.0 = destination;
+                        event_log.log(Event::Teleport { who: entity }.format(&names));
+                    }
+                }
+                ItemEffect::Status { kind, duration } => {
+                    StatusEffects::apply(
+                        &mut status_effects,
+                        entity,
+                        StatusEffect {
+                            kind,
+                            remaining: duration,
+                            source: entity,
+                        },
+                    );
+                    event_log.log(Event::StatusApplied { who: entity, kind }.format(&names));
+                }
+            }
+            entities.delete(intent.item).expect("couldn't delete");
+        }
+        intents.clear();
+    }
+}
@@ -0,0 +1,33 @@
+use specs::prelude::*;
+
+use crate::abilities::{Abilities, Energy};
+use crate::components::*;
+use crate::engine::LoopState;
+
+/// Ticks down ability cooldowns and regenerates energy for every entity that's starting a new
+/// turn.
+pub struct CooldownSystem;
+
+impl<'a> System<'a> for CooldownSystem {
+    type SystemData = (
+        ReadStorage<'a, NewTurn>,
+        WriteStorage<'a, Abilities>,
+        WriteStorage<'a, Energy>,
+        ReadExpect<'a, LoopState>,
+    );
+
+    fn run(&mut self, (new_turn, mut abilities, mut energy, loop_state): Self::SystemData) {
+        if *loop_state != LoopState::Looping {
+            return;
+        }
+
+        for (_new_turn, abilities) in (&new_turn, &mut abilities).join() {
+            for known in &mut abilities.0 {
+                known.cooldown = (known.cooldown - 1).max(0);
+            }
+        }
+        for (_new_turn, energy) in (&new_turn, &mut energy).join() {
+            energy.current = (energy.current + 1).min(energy.max);
+        }
+    }
+}
@@ -1,19 +1,114 @@
 use specs::prelude::*;
 
 use crate::components::*;
+use crate::event_log::{Event, EventLog};
 
+/// Applies queued damage, taking the target's resistances into account. Also remembers who dealt
+/// the damage, so that DeathSystem knows who to credit with a kill, and heals attackers that have
+/// lifesteal. Getting hurt also interrupts regeneration, and wakes sleeping entities up.
 pub struct DamageSystem;
 
 impl<'a> System<'a> for DamageSystem {
     type SystemData = (
+        Entities<'a>,
         WriteStorage<'a, CombatStats>,
         WriteStorage<'a, QueuedDamage>,
+        ReadStorage<'a, Resistances>,
+        WriteExpect<'a, EventLog>,
+        ReadStorage<'a, Name>,
+        WriteStorage<'a, LastAttacker>,
+        ReadStorage<'a, Lifesteal>,
+        WriteStorage<'a, Regeneration>,
+        WriteStorage<'a, Perception>,
+        ReadStorage<'a, Position>,
     );
 
-    fn run(&mut self, (mut stats, mut queues): Self::SystemData) {
-        for (stats, queue) in (&mut stats, &queues).join() {
-            stats.hp -= queue.0.iter().sum::<i32>();
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut stats,
+            mut queues,
+            resistances,
+            mut event_log,
+            names,
+            mut last_attackers,
+            lifesteal,
+            mut regeneration,
+            mut perception,
+            positions,
+        ): Self::SystemData,
+    ) {
+        // Attackers can't be healed while we're still going through the targets' stats, so
+        // lifesteal is applied at the end.
+        let mut stolen: Vec<(Entity, i32)> = vec![];
+        for (entity, stats, queue, resistances) in
+            (&entities, &mut stats, &queues, resistances.maybe()).join()
+        {
+            for damage in &queue.0 {
+                let mut amount = resistances.map_or(damage.amount, |resistances| {
+                    resistances.modify(damage.kind, damage.amount)
+                });
+                if damage.kind == DamageType::Physical {
+                    amount = (amount - stats.defense).max(0);
+                }
+                stats.hp -= amount;
+                if amount > 0 {
+                    if let Some(regeneration) = regeneration.get_mut(entity) {
+                        regeneration.interrupt();
+                    }
+                }
+                let attacker_pos = positions.get(damage.source).map(|position| position.0);
+                if let (Some(perception), Some(seen_at)) =
+                    (perception.get_mut(entity), attacker_pos)
+                {
+                    if perception.awareness == Awareness::Asleep {
+                        perception.awareness = Awareness::Hunting {
+                            target: damage.source,
+                            seen_at,
+                        };
+                        event_log.log(Event::WakeUp { who: entity }.format(&names));
+                    }
+                }
+                last_attackers
+                    .insert(entity, LastAttacker(damage.source))
+                    .expect("couldn't record attacker");
+                if let Some(lifesteal) = lifesteal.get(damage.source) {
+                    if damage.source != entity {
+                        stolen.push((damage.source, amount * lifesteal.0 / 100));
+                    }
+                }
+                let (from, to, kind) = (damage.source, entity, damage.kind);
+                let event = if damage.critical {
+                    Event::Critical {
+                        from,
+                        to,
+                        amount,
+                        kind,
+                    }
+                } else {
+                    Event::Damage {
+                        from,
+                        to,
+                        amount,
+                        kind,
+                    }
+                };
+                event_log.log(event.format(&names));
+            }
         }
         queues.clear();
+        for (who, amount) in stolen {
+            let healed = stats.get_mut(who).map_or(0, |stats| stats.heal(amount));
+            if healed > 0 {
+                event_log.log(
+                    Event::Heal {
+                        who,
+                        amount: healed,
+                    }
+                    .format(&names),
+                );
+            }
+        }
     }
 }
@@ -1,10 +1,22 @@
+use rand::{seq::SliceRandom, Rng};
 use specs::prelude::*;
 
+use crate::ai::AIComponent;
+use crate::blueprints::Blueprints;
 use crate::components::*;
-use crate::engine::LoopState;
+use crate::engine::{GameRng, LoopState};
 use crate::event_log::{Event, EventLog};
+use crate::geometry::*;
+use crate::map::Map;
+use crate::pack::{PackMember, Packs};
+use crate::tiles::TileId;
 
-/// Reaps dead entities.
+/// How many of the player's turns a corpse sticks around for before it decays.
+const CORPSE_DECAY_TURNS: i32 = 20;
+
+/// Reaps dead entities. Dead monsters are turned into corpses, which stick around for a while
+/// but no longer take part in combat or in their pack. They also drop everything they were carrying, plus
+/// whatever their loot table gives, and whoever killed them is awarded their experience.
 pub struct DeathSystem;
 
 impl<'a> System<'a> for DeathSystem {
@@ -15,11 +27,42 @@ impl<'a> System<'a> for DeathSystem {
         ReadStorage<'a, Name>,
         ReadStorage<'a, IsPlayer>,
         WriteExpect<'a, LoopState>,
+        Read<'a, LazyUpdate>,
+        ReadStorage<'a, Position>,
+        WriteStorage<'a, Inventory>,
+        ReadStorage<'a, LootTable>,
+        ReadExpect<'a, Blueprints>,
+        ReadExpect<'a, Map>,
+        WriteExpect<'a, GameRng>,
+        ReadStorage<'a, LastAttacker>,
+        ReadStorage<'a, ExperienceValue>,
+        WriteStorage<'a, Experience>,
+        ReadStorage<'a, PackMember>,
+        WriteExpect<'a, Packs>,
     );
 
     fn run(
         &mut self,
-        (entities, stats, mut event_log, names, is_player, mut loop_state): Self::SystemData,
+        (
+            entities,
+            stats,
+            mut event_log,
+            names,
+            is_player,
+            mut loop_state,
+            lazy,
+            positions,
+            mut inventories,
+            loot_tables,
+            blueprints,
+            map,
+            mut rng,
+            last_attackers,
+            experience_values,
+            mut experience,
+            pack_members,
+            mut packs,
+        ): Self::SystemData,
     ) {
         let mut to_die: Vec<Entity> = Vec::new();
         for (entity, stats, is_player) in (&entities, &stats, is_player.maybe()).join() {
@@ -31,8 +74,104 @@ impl<'a> System<'a> for DeathSystem {
             }
         }
         for dead in to_die {
-            entities.delete(dead).expect("couldn't delete");
             event_log.log(Event::Death { who: dead }.format(&names));
+            let killer = last_attackers.get(dead).map(|attacker| attacker.0);
+            if let (Some(killer), Some(value)) = (killer, experience_values.get(dead)) {
+                if let Some(experience) = experience.get_mut(killer).filter(|_| killer != dead) {
+                    experience.xp += value.0;
+                }
+            }
+            if is_player.contains(dead) {
+                entities.delete(dead).expect("couldn't delete");
+                continue;
+            }
+            if let Some(position) = positions.get(dead) {
+                let mut spots = drop_spots(&map, position.0, &mut rng);
+                if let Some(inventory) = inventories.get_mut(dead) {
+                    for item in inventory.items.drain(..) {
+                        lazy.insert(item, Position(spots.next().unwrap()));
+                        event_log.log(Event::Drop { who: dead, item }.format(&names));
+                    }
+                }
+                let drops = loot_tables.get(dead).map_or(&[][..], |table| &table.0[..]);
+                for drop in drops {
+                    if rng.0.gen_range(0, 100) >= drop.chance {
+                        continue;
+                    }
+                    let item = match blueprints.items.get(&drop.item) {
+                        Some(item) => item,
+                        None => continue,
+                    };
+                    if let Some(builder) =
+                        blueprints.build_item(&drop.item, lazy.create_entity(&entities))
+                    {
+                        builder.with(Position(spots.next().unwrap())).build();
+                        event_log.log(
+                            Event::Loot {
+                                who: dead,
+                                item: item.name.clone(),
+                            }
+                            .format(&names),
+                        );
+                    }
+                }
+            }
+            // Corpses aren't part of the pack any more, so they shouldn't hold on to a spot next to
+            // its prey.
+            if let Some(pack) = pack_members
+                .get(dead)
+                .and_then(|member| packs.get_mut(*member))
+            {
+                pack.claims.remove(&dead);
+            }
+            lazy.remove::<PackMember>(dead);
+            lazy.remove::<Perception>(dead);
+            lazy.remove::<LootTable>(dead);
+            lazy.remove::<ExperienceValue>(dead);
+            lazy.remove::<AIComponent>(dead);
+            lazy.remove::<Initiative>(dead);
+            lazy.remove::<Ready>(dead);
+            lazy.remove::<CombatStats>(dead);
+            lazy.remove::<BlocksMovement>(dead);
+            lazy.insert(
+                dead,
+                Visible {
+                    tile_id: TileId::Corpse,
+                },
+            );
+            if let Some(name) = names.get(dead) {
+                lazy.insert(
+                    dead,
+                    Name {
+                        name: format!("{}'s corpse", name.name),
+                    },
+                );
+            }
+            lazy.insert(
+                dead,
+                Decay {
+                    remaining: CORPSE_DECAY_TURNS,
+                },
+            );
         }
     }
 }
+
+/// Where items dropped at `center` land: the first one lands on `center` itself, and the rest are
+/// scattered around the walkable tiles next to it. Cycles forever, so it never runs out.
+fn drop_spots(
+    map: &Map,
+    center: WorldPoint,
+    rng: &mut GameRng,
+) -> impl Iterator<Item = WorldPoint> {
+    let mut around: Vec<WorldPoint> = points_within(center, 1)
+        .into_iter()
+        .filter(|point| *point != center && map.contains(*point) && !map.tile(*point).is_solid())
+        .collect();
+    around.shuffle(&mut rng.0);
+    std::iter::once(center)
+        .chain(around)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .cycle()
+}
@@ -0,0 +1,34 @@
+use specs::prelude::*;
+
+use crate::components::*;
+use crate::engine::LoopState;
+
+/// Counts down Decay components, deleting entities once they've fully decayed. Decaying things
+/// don't take turns of their own, so they count the player's turns instead.
+pub struct DecaySystem;
+
+impl<'a> System<'a> for DecaySystem {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, Decay>,
+        ReadStorage<'a, NewTurn>,
+        ReadStorage<'a, IsPlayer>,
+        ReadExpect<'a, LoopState>,
+    );
+
+    fn run(&mut self, (entities, mut decay, new_turn, is_player, loop_state): Self::SystemData) {
+        if *loop_state != LoopState::Looping {
+            return;
+        }
+        if (&is_player, &new_turn).join().next().is_none() {
+            return;
+        }
+
+        for (entity, decay) in (&entities, &mut decay).join() {
+            decay.remaining -= 1;
+            if decay.remaining <= 0 {
+                entities.delete(entity).expect("couldn't delete");
+            }
+        }
+    }
+}
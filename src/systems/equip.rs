@@ -0,0 +1,77 @@
+use specs::prelude::*;
+
+use crate::components::*;
+use crate::event_log::{Event, EventLog};
+
+/// Moves items between inventories and equipment slots.
+pub struct EquipSystem;
+
+impl<'a> System<'a> for EquipSystem {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, EquipIntent>,
+        WriteStorage<'a, UnequipIntent>,
+        WriteStorage<'a, Inventory>,
+        WriteStorage<'a, Equipment>,
+        ReadStorage<'a, Equippable>,
+        WriteExpect<'a, EventLog>,
+        ReadStorage<'a, Name>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut equips,
+            mut unequips,
+            mut inventories,
+            mut equipment,
+            equippables,
+            mut event_log,
+            names,
+        ): Self::SystemData,
+    ) {
+        for (entity, intent, inventory, equipment) in
+            (&entities, &equips, &mut inventories, &mut equipment).join()
+        {
+            let index = inventory.items.iter().position(|item| *item == intent.item);
+            let (index, equippable) = match (index, equippables.get(intent.item)) {
+                (Some(index), Some(equippable)) => (index, equippable),
+                _ => continue,
+            };
+            inventory.items.remove(index);
+            if let Some(old) = equipment.0.insert(equippable.slot, intent.item) {
+                inventory.items.push(old);
+                event_log.log(
+                    Event::Unequip {
+                        who: entity,
+                        item: old,
+                    }
+                    .format(&names),
+                );
+            }
+            event_log.log(
+                Event::Equip {
+                    who: entity,
+                    item: intent.item,
+                }
+                .format(&names),
+            );
+        }
+        equips.clear();
+
+        for (entity, intent, inventory, equipment) in
+            (&entities, &unequips, &mut inventories, &mut equipment).join()
+        {
+            if inventory.is_full() {
+                event_log.log(Event::InventoryFull { who: entity }.format(&names));
+                continue;
+            }
+            if let Some(item) = equipment.0.remove(&intent.slot) {
+                inventory.items.push(item);
+                event_log.log(Event::Unequip { who: entity, item }.format(&names));
+            }
+        }
+        unequips.clear();
+    }
+}
@@ -0,0 +1,91 @@
+use specs::prelude::*;
+
+use crate::components::*;
+use crate::engine::{GameRng, LoopState};
+use crate::event_log::{Event, EventLog};
+use crate::perks::PerkPool;
+
+/// How much max hp each level-up grants.
+const HP_PER_LEVEL: i32 = 5;
+/// How much attack each level-up grants.
+const ATTACK_PER_LEVEL: i32 = 1;
+
+/// Levels up entities that have built up enough experience. Each level makes the entity tougher
+/// and hit harder, and the extra hp is granted straight away. Entities that have Perks are also
+/// offered a choice of perks; if that's the player, the game pauses until they pick one.
+pub struct ExperienceSystem;
+
+impl<'a> System<'a> for ExperienceSystem {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, Experience>,
+        WriteStorage<'a, BaseStats>,
+        WriteStorage<'a, CombatStats>,
+        WriteExpect<'a, EventLog>,
+        ReadStorage<'a, Name>,
+        WriteStorage<'a, Perks>,
+        ReadExpect<'a, PerkPool>,
+        WriteExpect<'a, GameRng>,
+        ReadStorage<'a, IsPlayer>,
+        WriteExpect<'a, LoopState>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut experience,
+            mut base,
+            mut stats,
+            mut event_log,
+            names,
+            mut perks,
+            pool,
+            mut rng,
+            is_player,
+            mut loop_state,
+        ): Self::SystemData,
+    ) {
+        for (entity, experience, mut base, stats, mut perks) in (
+            &entities,
+            &mut experience,
+            (&mut base).maybe(),
+            &mut stats,
+            (&mut perks).maybe(),
+        )
+            .join()
+        {
+            while experience.xp >= experience.xp_needed() {
+                experience.xp -= experience.xp_needed();
+                experience.level += 1;
+                // StatsSystem will recompute the CombatStats from the BaseStats next time around,
+                // but bump them now too so that the new hp can be used right away.
+                if let Some(base) = base.as_mut() {
+                    base.max_hp += HP_PER_LEVEL;
+                    base.attack += ATTACK_PER_LEVEL;
+                }
+                stats.max_hp += HP_PER_LEVEL;
+                stats.hp += HP_PER_LEVEL;
+                stats.attack += ATTACK_PER_LEVEL;
+                event_log.log(
+                    Event::LevelUp {
+                        who: entity,
+                        level: experience.level,
+                        is_player: is_player.contains(entity),
+                    }
+                    .format(&names),
+                );
+                if let Some(perks) = perks.as_mut() {
+                    let offer = pool.offer(perks, &mut rng);
+                    if !offer.is_empty() {
+                        perks.offers.push(offer);
+                    }
+                }
+            }
+            let choosing = perks.is_some_and(|perks| !perks.offers.is_empty());
+            if choosing && is_player.contains(entity) && *loop_state == LoopState::Looping {
+                *loop_state = LoopState::ChoosingPerk;
+            }
+        }
+    }
+}
@@ -10,17 +10,40 @@ impl<'a> System<'a> for InitiativeSystem {
     type SystemData = (
         WriteStorage<'a, Initiative>,
         WriteStorage<'a, Ready>,
+        WriteStorage<'a, NewTurn>,
+        ReadStorage<'a, StatusEffects>,
+        ReadStorage<'a, Perception>,
         ReadExpect<'a, LoopState>,
         Entities<'a>,
     );
 
-    fn run(&mut self, (mut initiative, mut turn, loop_state, entities): Self::SystemData) {
+    fn run(
+        &mut self,
+        (mut initiative, mut turn, mut new_turn, effects, perception, loop_state, entities): Self::SystemData,
+    ) {
         if *loop_state != LoopState::Looping {
             return;
         }
 
-        for (initiative, entity) in (&mut initiative, &entities).join() {
+        new_turn.clear();
+        for (initiative, effects, entity) in (&mut initiative, effects.maybe(), &entities).join() {
             if initiative.tick() {
+                new_turn
+                    .insert(entity, NewTurn)
+                    .expect("can't set NewTurn component");
+                let haste = effects.map_or(0, |effects| effects.haste());
+                initiative.current = (initiative.current - haste).max(1);
+                if effects.is_some_and(|effects| effects.is_stunned()) {
+                    info!("entity {:?} is stunned and loses its turn", entity);
+                    continue;
+                }
+                let asleep = perception
+                    .get(entity)
+                    .is_some_and(|perception| perception.awareness == Awareness::Asleep);
+                if asleep {
+                    info!("entity {:?} is asleep and loses its turn", entity);
+                    continue;
+                }
                 turn.insert(entity, Ready)
                     .expect("can't set Ready component");
                 info!("entity {:?} is ready", entity);
@@ -0,0 +1,82 @@
+use specs::prelude::*;
+
+use crate::components::*;
+use crate::event_log::{Event, EventLog};
+use crate::map::Map;
+
+/// Resolves picking up and dropping items.
+pub struct InventorySystem;
+
+impl<'a> System<'a> for InventorySystem {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, PickUpIntent>,
+        WriteStorage<'a, DropIntent>,
+        WriteStorage<'a, Inventory>,
+        WriteStorage<'a, Position>,
+        ReadStorage<'a, Item>,
+        ReadExpect<'a, Map>,
+        WriteExpect<'a, EventLog>,
+        ReadStorage<'a, Name>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut pick_ups,
+            mut drops,
+            mut inventories,
+            mut positions,
+            items,
+            map,
+            mut event_log,
+            names,
+        ): Self::SystemData,
+    ) {
+        let mut picked_up: Vec<Entity> = vec![];
+        for (entity, _intent, inventory) in (&entities, &pick_ups, &mut inventories).join() {
+            let here = match positions.get(entity) {
+                Some(position) => position.0,
+                None => continue,
+            };
+            for &item in map.entities_at(here) {
+                if !items.contains(item) || picked_up.contains(&item) {
+                    continue;
+                }
+                if inventory.is_full() {
+                    event_log.log(Event::InventoryFull { who: entity }.format(&names));
+                    break;
+                }
+                inventory.items.push(item);
+                picked_up.push(item);
+                event_log.log(Event::PickUp { who: entity, item }.format(&names));
+            }
+        }
+        for item in picked_up {
+            positions.remove(item);
+        }
+        pick_ups.clear();
+
+        for (entity, intent, inventory) in (&entities, &drops, &mut inventories).join() {
+            let here = match positions.get(entity) {
+                Some(position) => *position,
+                None => continue,
+            };
+            if let Some(index) = inventory.items.iter().position(|item| *item == intent.item) {
+                inventory.items.remove(index);
+                positions
+                    .insert(intent.item, here)
+                    .expect("failed to place dropped item");
+                event_log.log(
+                    Event::Drop {
+                        who: entity,
+                        item: intent.item,
+                    }
+                    .format(&names),
+                );
+            }
+        }
+        drops.clear();
+    }
+}
@@ -1,9 +1,17 @@
 use specs::prelude::*;
+use specs::storage::ComponentEvent;
 
 use crate::components::*;
 use crate::map::Map;
 
-pub struct MapUpdateSystem;
+/// Keeps track of which entities are on which tile of the map. Instead of redoing this from
+/// scratch every time, it only looks at the entities whose Position or BlocksMovement changed
+/// since it last ran. Needs to be set up before it's run, so that it can listen for those changes.
+#[derive(Default)]
+pub struct MapUpdateSystem {
+    position_events: Option<ReaderId<ComponentEvent>>,
+    blocking_events: Option<ReaderId<ComponentEvent>>,
+}
 
 impl<'a> System<'a> for MapUpdateSystem {
     type SystemData = (
@@ -13,10 +21,182 @@ impl<'a> System<'a> for MapUpdateSystem {
         Entities<'a>,
     );
 
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.position_events = Some(WriteStorage::<Position>::fetch(world).register_reader());
+        self.blocking_events = Some(WriteStorage::<BlocksMovement>::fetch(world).register_reader());
+    }
+
     fn run(&mut self, (mut map, positions, blocked, entities): Self::SystemData) {
-        map.clear_entities();
-        for (position, blocked, entity) in (&positions, blocked.maybe(), &entities).join() {
-            map.add_entity(position.0, entity, blocked.is_some());
+        let mut changed = BitSet::new();
+        let position_events = self
+            .position_events
+            .as_mut()
+            .expect("MapUpdateSystem wasn't set up");
+        let blocking_events = self
+            .blocking_events
+            .as_mut()
+            .expect("MapUpdateSystem wasn't set up");
+        for event in positions
+            .channel()
+            .read(position_events)
+            .chain(blocked.channel().read(blocking_events))
+        {
+            match event {
+                ComponentEvent::Inserted(id)
+                | ComponentEvent::Modified(id)
+                | ComponentEvent::Removed(id) => changed.add(*id),
+            };
+        }
+
+        if !map.is_indexed() {
+            map.clear_entities();
+            for (position, blocked, entity) in (&positions, blocked.maybe(), &entities).join() {
+                map.add_entity(position.0, entity, blocked.is_some());
+            }
+            map.mark_indexed();
+            return;
+        }
+        for id in (&changed).join() {
+            map.remove_entity(id);
+            let entity = entities.entity(id);
+            if !entities.is_alive(entity) {
+                continue;
+            }
+            if let Some(position) = positions.get(entity) {
+                map.add_entity(position.0, entity, blocked.contains(entity));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::{AIComponent, PlayerAI};
+    use crate::blueprints::Blueprints;
+    use crate::engine::{Action, Engine, PlayerId};
+    use crate::event_log::EventLog;
+    use crate::geometry::WorldPoint;
+
+    /// Checks that the map's index matches one built from scratch out of the current positions.
+    fn assert_matches_rebuild(world: &World) {
+        let map = world.fetch::<Map>();
+        let mut rebuilt = Map::new(map.width(), map.height());
+        let positions = world.read_storage::<Position>();
+        let blocked = world.read_storage::<BlocksMovement>();
+        for (position, blocked, entity) in (&positions, blocked.maybe(), &world.entities()).join() {
+            rebuilt.add_entity(position.0, entity, blocked.is_some());
         }
+        for y in 0..map.height() {
+            for x in 0..map.width() {
+                let point = WorldPoint::new(x, y);
+                assert_eq!(
+                    map.entities_at(point),
+                    rebuilt.entities_at(point),
+                    "at {:?}",
+                    point
+                );
+                assert_eq!(
+                    map.blockers(point),
+                    rebuilt.blockers(point),
+                    "at {:?}",
+                    point
+                );
+            }
+        }
+    }
+
+    fn act(engine: &mut Engine, action: Action) {
+        engine.set_action(action);
+        engine.tick();
+        assert_matches_rebuild(&engine.world);
+    }
+
+    #[test]
+    fn incremental_index_matches_rebuild() {
+        let mut engine = Engine::new(0);
+        let base = BaseStats {
+            max_hp: 20,
+            attack: 3,
+            accuracy: 80,
+            evasion: 10,
+            crit_chance: 0,
+            damage_type: DamageType::Physical,
+            defense: 0,
+            stealth: 0,
+        };
+        let player = engine
+            .world
+            .create_entity()
+            .with(Name {
+                name: "you".to_string(),
+            })
+            .with(Position((2, 2).into()))
+            .with(AIComponent(Box::new(PlayerAI)))
+            .with(IsPlayer)
+            .with(Faction::Player)
+            .with(base)
+            .with(base.derive(base.max_hp, &[]))
+            .with(Equipment::default())
+            .with(Initiative::new(10))
+            .with(Inventory::new(5))
+            .with(BlocksMovement)
+            .build();
+        let ally = engine
+            .world
+            .create_entity()
+            .with(Name {
+                name: "ally".to_string(),
+            })
+            .with(Position((4, 2).into()))
+            .with(Faction::Player)
+            .with(BlocksMovement)
+            .build();
+        let blueprints = Blueprints::load();
+        let potion = blueprints.spawn_item(&mut engine.world, "healing_potion", (4, 3).into());
+        let monster = blueprints.spawn_monster(&mut engine.world, "swarmer", (8, 2).into());
+        engine.world.write_storage::<AIComponent>().remove(monster);
+        engine.world.insert(blueprints);
+        engine.world.insert(PlayerId(player));
+        engine.world.insert(EventLog::new());
+        engine.world.insert(Map::new(12, 8));
+        engine.tick();
+        assert_matches_rebuild(&engine.world);
+
+        let position = |engine: &Engine, entity| {
+            engine
+                .world
+                .read_storage::<Position>()
+                .get(entity)
+                .map(|position| position.0)
+        };
+        let motion = |x, y| Action::Move {
+            motion: (x, y).into(),
+        };
+
+        act(&mut engine, motion(1, 0));
+        assert_eq!(position(&engine, player), Some((3, 2).into()));
+
+        act(&mut engine, motion(1, 0));
+        assert_eq!(position(&engine, player), Some((4, 2).into()));
+        assert_eq!(position(&engine, ally), Some((3, 2).into()));
+
+        act(&mut engine, motion(0, 1));
+        act(&mut engine, Action::PickUp);
+        assert_eq!(position(&engine, potion), None);
+
+        act(&mut engine, Action::Drop { item: potion });
+        assert_eq!(position(&engine, potion), Some((4, 3).into()));
+
+        engine
+            .world
+            .write_storage::<CombatStats>()
+            .get_mut(monster)
+            .expect("monster has no stats")
+            .hp = 0;
+        act(&mut engine, Action::Wait);
+        assert!(!engine.world.read_storage::<CombatStats>().contains(monster));
+        assert_eq!(position(&engine, monster), Some((8, 2).into()));
     }
 }
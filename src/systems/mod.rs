@@ -4,9 +4,33 @@ mod map_update;
 pub use map_update::MapUpdateSystem;
 mod attack;
 pub use attack::AttackSystem;
+mod ranged;
+pub use ranged::RangedAttackSystem;
 mod movement;
 pub use movement::MovementSystem;
 mod damage;
 pub use damage::DamageSystem;
 mod death;
 pub use death::DeathSystem;
+mod decay;
+pub use decay::DecaySystem;
+mod status;
+pub use status::StatusEffectSystem;
+mod cooldown;
+pub use cooldown::CooldownSystem;
+mod ability;
+pub use ability::AbilitySystem;
+mod inventory;
+pub use inventory::InventorySystem;
+mod equip;
+pub use equip::EquipSystem;
+mod stats;
+pub use stats::StatsSystem;
+mod consumable;
+pub use consumable::ConsumableSystem;
+mod experience;
+pub use experience::ExperienceSystem;
+mod regeneration;
+pub use regeneration::RegenerationSystem;
+mod perception;
+pub use perception::PerceptionSystem;
@@ -1,24 +1,244 @@
 use specs::prelude::*;
 
 use crate::components::*;
+use crate::engine::{Noise, Noises};
+use crate::geometry::{WorldPoint, WorldVector};
 use crate::map::Map;
 
+/// How far away footsteps can be heard.
+const MOVE_NOISE: i32 = 3;
+
+/// Moves entities that want to move. Moving into an ally swaps places with it, except that the
+/// player is never moved around by its allies.
+///
+/// Moves are applied one at a time, and the map is updated after each one, so that two entities
+/// can never end up on the same tile. If an AI's move is blocked by an entity that got there
+/// first (allies that have already moved don't get swapped back), it sidesteps to a free tile
+/// that's next to both where it is and where it wanted to go; if there isn't one, it stays put.
+/// The player's moves are never rerouted.
 pub struct MovementSystem;
 
 impl<'a> System<'a> for MovementSystem {
     type SystemData = (
+        Entities<'a>,
         WriteStorage<'a, MoveIntent>,
         WriteStorage<'a, Position>,
-        ReadExpect<'a, Map>,
+        WriteExpect<'a, Map>,
+        ReadStorage<'a, Faction>,
+        ReadStorage<'a, BlocksMovement>,
+        ReadStorage<'a, IsPlayer>,
+        WriteExpect<'a, Noises>,
     );
 
-    fn run(&mut self, (mut intents, mut positions, map): Self::SystemData) {
-        for (intent, position) in (&intents, &mut positions).join() {
-            let new_pos = position.0 + intent.0;
-            if !map.is_blocked(new_pos) {
+    fn run(
+        &mut self,
+        (entities, mut intents, mut positions, mut map, factions, blocking, players, mut noises): Self::SystemData,
+    ) {
+        let moves: Vec<(Entity, MoveIntent)> = (&entities, &intents)
+            .join()
+            .map(|(entity, intent)| (entity, *intent))
+            .collect();
+        let mut moved = vec![];
+        for (entity, intent) in moves {
+            let old_pos = match positions.get(entity) {
+                Some(position) => position.0,
+                None => continue,
+            };
+            let wanted = old_pos + intent.0;
+            if !map.contains(wanted) {
+                continue;
+            }
+            let swap_with = map.blockers(wanted).filter(|blocker| {
+                Faction::between(&factions, entity, *blocker) == Relation::Allied
+                    && !players.contains(*blocker)
+                    && !moved.contains(blocker)
+            });
+            let new_pos = if !map.is_blocked(wanted) || swap_with.is_some() {
+                wanted
+            } else if map.tile(wanted).is_solid() || players.contains(entity) {
+                continue;
+            } else {
+                match sidestep(&map, old_pos, intent.0) {
+                    Some(new_pos) => new_pos,
+                    None => continue,
+                }
+            };
+            let swap_with = swap_with.filter(|_| new_pos == wanted);
+
+            map.remove_entity(entity.id());
+            if let Some(ally) = swap_with {
+                map.add_entity(old_pos, ally, blocking.contains(ally));
+                if let Some(position) = positions.get_mut(ally) {
+                    position.0 = old_pos;
+                }
+            }
+            map.add_entity(new_pos, entity, blocking.contains(entity));
+            if let Some(position) = positions.get_mut(entity) {
                 position.0 = new_pos;
             }
+            moved.push(entity);
+            noises.0.push(Noise {
+                source: entity,
+                origin: new_pos,
+                radius: MOVE_NOISE,
+            });
         }
         intents.clear();
     }
 }
+
+/// A free tile that's one step from `from`, and also next to where `motion` would have taken it.
+/// These are the tiles on either side of the one it wanted.
+fn sidestep(map: &Map, from: WorldPoint, motion: WorldVector) -> Option<WorldPoint> {
+    let alternatives = if motion.x != 0 && motion.y != 0 {
+        [WorldVector::new(motion.x, 0), WorldVector::new(0, motion.y)]
+    } else {
+        // Turn the motion 45 degrees either way.
+        [
+            WorldVector::new(motion.x - motion.y, motion.y + motion.x),
+            WorldVector::new(motion.x + motion.y, motion.y - motion.x),
+        ]
+    };
+    alternatives
+        .iter()
+        .map(|alternative| from + *alternative)
+        .find(|point| map.contains(*point) && !map.is_blocked(*point))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::TileType;
+
+    fn test_world() -> World {
+        let mut world = World::new();
+        world.register::<MoveIntent>();
+        world.register::<Position>();
+        world.register::<Faction>();
+        world.register::<BlocksMovement>();
+        world.register::<IsPlayer>();
+        world.insert(Map::new(10, 10));
+        world.insert(Noises::default());
+        world
+    }
+
+    /// Puts a blocking entity of the given faction on the map. Entities created earlier move
+    /// first.
+    fn spawn(world: &mut World, x: i32, y: i32, faction: Faction) -> Entity {
+        let entity = world
+            .create_entity()
+            .with(Position((x, y).into()))
+            .with(faction)
+            .with(BlocksMovement)
+            .build();
+        world
+            .fetch_mut::<Map>()
+            .add_entity((x, y).into(), entity, true);
+        entity
+    }
+
+    fn order(world: &World, entity: Entity, x: i32, y: i32) {
+        world
+            .write_storage::<MoveIntent>()
+            .insert(entity, MoveIntent((x, y).into()))
+            .expect("couldn't add move intent");
+    }
+
+    fn position(world: &World, entity: Entity) -> WorldPoint {
+        world.read_storage::<Position>().get(entity).unwrap().0
+    }
+
+    #[test]
+    fn contending_movers_dont_share_a_tile() {
+        let mut world = test_world();
+        let first = spawn(&mut world, 1, 1, Faction::Monsters);
+        let second = spawn(&mut world, 3, 1, Faction::Wildlife);
+        order(&world, first, 1, 0);
+        order(&world, second, -1, 0);
+        MovementSystem.run_now(&world);
+
+        assert_eq!(position(&world, first), (2, 1).into());
+        let sidestepped = position(&world, second);
+        assert!([(2, 0).into(), (2, 2).into()].contains(&sidestepped));
+        let map = world.fetch::<Map>();
+        assert_eq!(map.blockers((2, 1).into()), Some(first));
+        assert_eq!(map.blockers(sidestepped), Some(second));
+    }
+
+    #[test]
+    fn allies_are_swapped_only_if_they_havent_moved() {
+        let mut world = test_world();
+        let waiting = spawn(&mut world, 2, 1, Faction::Monsters);
+        let swapper = spawn(&mut world, 1, 1, Faction::Monsters);
+        order(&world, swapper, 1, 0);
+        MovementSystem.run_now(&world);
+        assert_eq!(position(&world, swapper), (2, 1).into());
+        assert_eq!(position(&world, waiting), (1, 1).into());
+
+        let mut world = test_world();
+        let mover = spawn(&mut world, 3, 1, Faction::Monsters);
+        let follower = spawn(&mut world, 1, 1, Faction::Monsters);
+        order(&world, mover, -1, 0);
+        order(&world, follower, 1, 0);
+        MovementSystem.run_now(&world);
+        assert_eq!(position(&world, mover), (2, 1).into());
+        assert_ne!(position(&world, follower), (2, 1).into());
+        assert_ne!(position(&world, follower), (1, 1).into());
+    }
+
+    #[test]
+    fn player_is_never_rerouted_or_swapped() {
+        let mut world = test_world();
+        let player = spawn(&mut world, 1, 1, Faction::Player);
+        world
+            .write_storage::<IsPlayer>()
+            .insert(player, IsPlayer)
+            .unwrap();
+        spawn(&mut world, 2, 1, Faction::Wildlife);
+        let ally = spawn(&mut world, 0, 1, Faction::Player);
+        order(&world, player, 1, 0);
+        order(&world, ally, 1, 0);
+        MovementSystem.run_now(&world);
+
+        assert_eq!(position(&world, player), (1, 1).into());
+        assert_ne!(position(&world, ally), (1, 1).into());
+    }
+
+    #[test]
+    fn blocked_movers_sidestep_if_they_can() {
+        let mut world = test_world();
+        spawn(&mut world, 2, 1, Faction::Wildlife);
+        world
+            .fetch_mut::<Map>()
+            .set_tile((2, 0).into(), TileType::Wall);
+        let mover = spawn(&mut world, 1, 1, Faction::Monsters);
+        order(&world, mover, 1, 0);
+        MovementSystem.run_now(&world);
+        assert_eq!(position(&world, mover), (2, 2).into());
+
+        // With both sides blocked as well, it stays put.
+        spawn(&mut world, 3, 2, Faction::Wildlife);
+        spawn(&mut world, 3, 1, Faction::Wildlife);
+        spawn(&mut world, 3, 3, Faction::Wildlife);
+        order(&world, mover, 1, 0);
+        MovementSystem.run_now(&world);
+        assert_eq!(position(&world, mover), (2, 2).into());
+
+        // Walls aren't sidestepped.
+        world
+            .fetch_mut::<Map>()
+            .set_tile((2, 3).into(), TileType::Wall);
+        order(&world, mover, 0, 1);
+        MovementSystem.run_now(&world);
+        assert_eq!(position(&world, mover), (2, 2).into());
+    }
+
+    #[test]
+    fn sidestep_tries_the_tiles_on_either_side() {
+        let map = Map::new(10, 10);
+        let from = (5, 5).into();
+        assert_eq!(sidestep(&map, from, (1, 1).into()), Some((6, 5).into()));
+        assert_eq!(sidestep(&map, from, (1, 0).into()), Some((6, 6).into()));
+        assert_eq!(sidestep(&map, (0, 0).into(), (0, -1).into()), None);
+    }
+}
@@ -0,0 +1,227 @@
+use log::info;
+use rand::Rng;
+use specs::prelude::*;
+
+use crate::components::*;
+use crate::engine::{GameRng, LoopState, Noises};
+use crate::event_log::{Event, EventLog};
+use crate::geometry::distance;
+use crate::map::Map;
+use crate::pack::{PackMember, Packs, Sighting};
+
+/// How many turns an entity keeps looking for an enemy it's lost sight of.
+const SEARCH_TURNS: i32 = 10;
+
+/// Updates what each entity with Perception knows at the start of its turn: which enemy it can
+/// see, or where it last saw one. Pack members also know where the rest of their pack last saw an
+/// enemy, and call the pack over when they spot something first.
+///
+/// Noises are heard straight away instead of at the start of the hearer's turn. An enemy's noise
+/// wakes sleeping entities up and sends unaware ones to look for where it came from. Enemies with
+/// stealth have a chance of going unnoticed, both when they come into view and when they make a
+/// noise.
+pub struct PerceptionSystem;
+
+impl<'a> System<'a> for PerceptionSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, NewTurn>,
+        WriteStorage<'a, Perception>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, CombatStats>,
+        ReadStorage<'a, Faction>,
+        ReadStorage<'a, PackMember>,
+        ReadStorage<'a, Name>,
+        ReadExpect<'a, Map>,
+        ReadExpect<'a, LoopState>,
+        WriteExpect<'a, Packs>,
+        WriteExpect<'a, EventLog>,
+        WriteExpect<'a, Noises>,
+        WriteExpect<'a, GameRng>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            new_turn,
+            mut perception,
+            positions,
+            stats,
+            factions,
+            pack_members,
+            names,
+            map,
+            loop_state,
+            mut packs,
+            mut event_log,
+            mut noises,
+            mut rng,
+        ): Self::SystemData,
+    ) {
+        if *loop_state != LoopState::Looping {
+            return;
+        }
+
+        for noise in noises.0.drain(..) {
+            let heard_on = map.noise_area(noise.origin, noise.radius);
+            let chance = notice_chance(stats.get(noise.source));
+            for (entity, perception, position) in (&entities, &mut perception, &positions).join() {
+                if perception.target().is_some()
+                    || !heard_on.contains(&position.0)
+                    || Faction::between(&factions, entity, noise.source) != Relation::Hostile
+                    || rng.0.gen_range(0, 100) >= chance
+                {
+                    continue;
+                }
+                if perception.awareness == Awareness::Asleep {
+                    event_log.log(Event::WakeUp { who: entity }.format(&names));
+                }
+                perception.awareness = Awareness::Searching {
+                    last_known: noise.origin,
+                    turns_left: SEARCH_TURNS,
+                };
+            }
+        }
+
+        // Forget sightings whose spotter has died or lost track of the target in the meantime.
+        for pack in &mut packs.0 {
+            let still_seen = pack.sighting.is_some_and(|sighting| {
+                entities.is_alive(sighting.spotter)
+                    && stats.contains(sighting.spotter)
+                    && perception
+                        .get(sighting.spotter)
+                        .and_then(|perception| perception.target())
+                        == Some(sighting.target)
+            });
+            if !still_seen {
+                pack.sighting = None;
+            }
+        }
+
+        let mut alerts = Vec::new();
+        for (entity, _new_turn, perception, position, member) in (
+            &entities,
+            &new_turn,
+            &mut perception,
+            &positions,
+            pack_members.maybe(),
+        )
+            .join()
+        {
+            let visible: Vec<Entity> = map
+                .field_of_view(position.0, perception.sight_radius)
+                .into_iter()
+                .flat_map(|point| map.entities_at(point).iter().copied())
+                .filter(|other| {
+                    stats.contains(*other)
+                        && Faction::between(&factions, entity, *other) == Relation::Hostile
+                })
+                .collect();
+            let seen = |other: Entity| Some((other, positions.get(other)?.0));
+            let nearest = visible
+                .iter()
+                .copied()
+                .filter_map(seen)
+                .min_by_key(|(_, other_pos)| distance(position.0, *other_pos))
+                // Sleeping entities don't see anything, and an entity that isn't already hunting
+                // might not notice a sneaky enemy.
+                .filter(|(other, _)| match perception.awareness {
+                    Awareness::Asleep => false,
+                    Awareness::Hunting { .. } => true,
+                    _ => rng.0.gen_range(0, 100) < notice_chance(stats.get(*other)),
+                });
+
+            let awareness = match (perception.awareness, nearest) {
+                (Awareness::Asleep, _) => Awareness::Asleep,
+                // Stick with the current target for as long as it can be seen.
+                (Awareness::Hunting { target, seen_at }, _) if visible.contains(&target) => {
+                    Awareness::Hunting {
+                        target,
+                        seen_at: seen(target).map_or(seen_at, |(_, pos)| pos),
+                    }
+                }
+                (_, Some((target, seen_at))) => Awareness::Hunting { target, seen_at },
+                (Awareness::Hunting { target, seen_at }, None) if stats.contains(target) => {
+                    Awareness::Searching {
+                        last_known: seen_at,
+                        turns_left: SEARCH_TURNS,
+                    }
+                }
+                (
+                    Awareness::Searching {
+                        last_known,
+                        turns_left,
+                    },
+                    None,
+                ) if turns_left > 1 => Awareness::Searching {
+                    last_known,
+                    turns_left: turns_left - 1,
+                },
+                (_, None) => Awareness::Unaware,
+            };
+            let pack = member.and_then(|member| Some((*member, packs.get_mut(*member)?)));
+            let awareness = match (pack, awareness) {
+                (Some((member, pack)), Awareness::Hunting { target, seen_at }) => {
+                    if pack.sighting.is_none() {
+                        event_log.log(Event::Alert { who: entity }.format(&names));
+                        alerts.push(member);
+                    }
+                    pack.sighting = Some(Sighting {
+                        target,
+                        seen_at,
+                        spotter: entity,
+                    });
+                    awareness
+                }
+                (Some((_, pack)), _) => {
+                    pack.claims.remove(&entity);
+                    if pack.sighting.map(|sighting| sighting.spotter) == Some(entity) {
+                        pack.sighting = None;
+                    }
+                    // Members that can't see the target themselves only know where it was seen.
+                    // Sleeping members sleep through it; only noise or getting hurt wakes them.
+                    match pack.sighting {
+                        Some(Sighting {
+                            target, seen_at, ..
+                        }) if stats.contains(target) && awareness != Awareness::Asleep => {
+                            Awareness::Searching {
+                                last_known: seen_at,
+                                turns_left: SEARCH_TURNS,
+                            }
+                        }
+                        _ => awareness,
+                    }
+                }
+                (None, _) => awareness,
+            };
+            if std::mem::discriminant(&awareness) != std::mem::discriminant(&perception.awareness) {
+                info!(
+                    "{:?} went from {:?} to {:?}",
+                    entity, perception.awareness, awareness
+                );
+            }
+            perception.awareness = awareness;
+        }
+
+        // Bring the rest of the pack in on anything that was just spotted, without waiting for
+        // their turns to come around. Members that are asleep don't hear the call.
+        for member in alerts {
+            let sighting = match packs.get_mut(member).and_then(|pack| pack.sighting) {
+                Some(sighting) => sighting,
+                None => continue,
+            };
+            for (_, perception) in (&pack_members, &mut perception)
+                .join()
+                .filter(|(other, _)| **other == member)
+            {
+                if perception.target().is_none() && perception.awareness != Awareness::Asleep {
+                    perception.awareness = Awareness::Searching {
+                        last_known: sighting.seen_at,
+                        turns_left: SEARCH_TURNS,
+                    };
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use specs::prelude::*;
+
+use crate::components::*;
+use crate::engine::Projectiles;
+use crate::event_log::{Event, EventLog};
+use crate::map::Map;
+
+/// Fires projectiles. If a projectile hits an entity, this turns into a regular attack on it,
+/// which is then resolved by AttackSystem.
+pub struct RangedAttackSystem;
+
+impl<'a> System<'a> for RangedAttackSystem {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, RangedAttackIntent>,
+        WriteStorage<'a, RangedWeapon>,
+        ReadStorage<'a, Position>,
+        WriteStorage<'a, AttackIntent>,
+        ReadExpect<'a, Map>,
+        WriteExpect<'a, Projectiles>,
+        WriteExpect<'a, EventLog>,
+        ReadStorage<'a, Name>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut intents,
+            mut weapons,
+            positions,
+            mut attacks,
+            map,
+            mut projectiles,
+            mut event_log,
+            names,
+        ): Self::SystemData,
+    ) {
+        for (entity, intent, weapon, position) in
+            (&entities, &intents, &mut weapons, &positions).join()
+        {
+            if weapon.ammo <= 0 {
+                event_log.log(Event::OutOfAmmo { who: entity }.format(&names));
+                continue;
+            }
+            weapon.ammo -= 1;
+            let path = map.line_of_fire(position.0, intent.target, weapon.range);
+            if let Some(target) = path.last().and_then(|end| map.blockers(*end)) {
+                attacks
+                    .insert(entity, AttackIntent { target })
+                    .expect("failed to insert attack intent");
+            }
+            projectiles.0.push(path);
+        }
+        intents.clear();
+    }
+}
@@ -0,0 +1,39 @@
+use specs::prelude::*;
+
+use crate::components::*;
+use crate::engine::LoopState;
+
+/// Restores hp to every entity with Regeneration that's starting a new turn, once enough turns
+/// have passed. DamageSystem pauses regeneration whenever an entity gets hurt.
+pub struct RegenerationSystem;
+
+impl<'a> System<'a> for RegenerationSystem {
+    type SystemData = (
+        ReadStorage<'a, NewTurn>,
+        WriteStorage<'a, Regeneration>,
+        WriteStorage<'a, CombatStats>,
+        ReadExpect<'a, LoopState>,
+    );
+
+    fn run(&mut self, (new_turn, mut regeneration, mut stats, loop_state): Self::SystemData) {
+        if *loop_state != LoopState::Looping {
+            return;
+        }
+
+        for (_new_turn, regeneration, stats) in (&new_turn, &mut regeneration, &mut stats).join() {
+            if regeneration.paused > 0 {
+                regeneration.paused -= 1;
+                continue;
+            }
+            if stats.hp >= stats.max_hp {
+                regeneration.progress = 0;
+                continue;
+            }
+            regeneration.progress += 1;
+            if regeneration.progress >= regeneration.interval {
+                regeneration.progress = 0;
+                stats.heal(1);
+            }
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use specs::prelude::*;
+
+use crate::components::*;
+
+/// Recomputes the CombatStats of entities that have BaseStats, and the regeneration rate of
+/// entities that regenerate, taking their equipment into account.
+pub struct StatsSystem;
+
+impl<'a> System<'a> for StatsSystem {
+    type SystemData = (
+        ReadStorage<'a, BaseStats>,
+        WriteStorage<'a, CombatStats>,
+        ReadStorage<'a, Equipment>,
+        ReadStorage<'a, Equippable>,
+        WriteStorage<'a, Regeneration>,
+    );
+
+    fn run(
+        &mut self,
+        (base, mut stats, equipment, equippables, mut regeneration): Self::SystemData,
+    ) {
+        for (base, stats, equipment) in (&base, &mut stats, equipment.maybe()).join() {
+            *stats = base.derive(stats.hp, bonuses(equipment, &equippables));
+        }
+        for (regeneration, equipment) in (&mut regeneration, equipment.maybe()).join() {
+            let faster: i32 = bonuses(equipment, &equippables)
+                .map(|bonus| bonus.regeneration)
+                .sum();
+            regeneration.interval = (regeneration.base_interval - faster).max(1);
+        }
+    }
+}
+
+/// The stat bonuses granted by everything in `equipment`, in the order of `EquipSlot::ALL`. The
+/// order matters for bonuses that override rather than add up, like damage types, so it mustn't
+/// depend on the map's iteration order.
+fn bonuses<'b>(
+    equipment: Option<&'b Equipment>,
+    equippables: &'b ReadStorage<Equippable>,
+) -> impl Iterator<Item = &'b StatBonus> {
+    equipment
+        .into_iter()
+        .flat_map(|equipment| {
+            EquipSlot::ALL
+                .iter()
+                .filter_map(move |slot| equipment.0.get(slot))
+        })
+        .filter_map(move |item| equippables.get(*item))
+        .map(|equippable| &equippable.bonus)
+}
@@ -0,0 +1,64 @@
+use specs::prelude::*;
+
+use crate::components::*;
+use crate::engine::LoopState;
+use crate::event_log::{Event, EventLog};
+
+/// Ticks down status effects on every entity that's starting a new turn, applying their per-turn
+/// behaviour and removing them once they wear off. Stun and haste are handled by
+/// InitiativeSystem.
+pub struct StatusEffectSystem;
+
+impl<'a> System<'a> for StatusEffectSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, NewTurn>,
+        WriteStorage<'a, StatusEffects>,
+        WriteStorage<'a, QueuedDamage>,
+        WriteExpect<'a, EventLog>,
+        ReadStorage<'a, Name>,
+        ReadExpect<'a, LoopState>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, new_turn, mut effects, mut queues, mut event_log, names, loop_state): Self::SystemData,
+    ) {
+        if *loop_state != LoopState::Looping {
+            return;
+        }
+
+        for (entity, _new_turn, effects) in (&entities, &new_turn, &mut effects).join() {
+            for effect in &mut effects.0 {
+                let damage = match effect.kind {
+                    StatusKind::Poison { damage } => Some((damage, DamageType::Poison)),
+                    StatusKind::Bleed { damage } => Some((damage, DamageType::Physical)),
+                    StatusKind::Stun | StatusKind::Haste { .. } => None,
+                };
+                if let Some((amount, kind)) = damage {
+                    QueuedDamage::add(
+                        &mut queues,
+                        entity,
+                        Damage {
+                            amount,
+                            kind,
+                            source: effect.source,
+                            critical: false,
+                        },
+                    );
+                }
+                effect.remaining -= 1;
+            }
+            for expired in effects.0.iter().filter(|effect| effect.remaining <= 0) {
+                event_log.log(
+                    Event::StatusExpired {
+                        who: entity,
+                        kind: expired.kind,
+                    }
+                    .format(&names),
+                );
+            }
+            effects.0.retain(|effect| effect.remaining > 0);
+        }
+    }
+}
@@ -4,13 +4,24 @@ use quicksilver::{
     graphics::{Graphics, Image},
     Result,
 };
+use serde::Deserialize;
 use std::collections::HashMap;
 use std::path::Path;
 
-#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash, IntoEnumIterator)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash, IntoEnumIterator, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum TileId {
     Player,
     Grunt,
+    Corpse,
+    Potion,
+    Scroll,
+    Sword,
+    Armor,
+    Ring,
+    Amulet,
+    Hound,
+    Archer,
 }
 
 impl TileId {
@@ -18,6 +29,15 @@ impl TileId {
         match self {
             TileId::Player => "player",
             TileId::Grunt => "grunt",
+            TileId::Corpse => "corpse",
+            TileId::Potion => "potion",
+            TileId::Scroll => "scroll",
+            TileId::Sword => "sword",
+            TileId::Armor => "armor",
+            TileId::Ring => "ring",
+            TileId::Amulet => "amulet",
+            TileId::Hound => "hound",
+            TileId::Archer => "archer",
         }
     }
 }